@@ -1,19 +1,31 @@
 use anyhow::{Context, anyhow};
 use cktool::{
-    declare::{ERROR_REQUEST_DELAY_SEC, TOO_MANY_REQUESTS_DELAY_SEC},
-    request,
+    config::Config,
+    declare::{
+        CONNECT_TIMEOUT_SEC, ERROR_REQUEST_DELAY_SEC, Exit, LOG_BODY_LIMIT, REQUEST_TIMEOUT_SEC,
+        STALL_TIMEOUT_SEC, TOO_MANY_REQUESTS_DELAY_SEC,
+    },
+    logging::{DOWNLOAD, LogOptions, VERBOSE_LEVEL},
+    request::{self, RequestOptions},
+    utils::{
+        Bandwidth, RateSchedule, SNIFF_BYTES, Shutdown, Signal, check_magic, parse_size, read_head,
+    },
 };
 use clap::Parser;
 use colored::Colorize;
-use futures_util::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::{StatusCode, header::RANGE};
-use std::{cmp::min, path::Path, time::Duration};
+use reqwest::{
+    StatusCode,
+    header::{CONTENT_TYPE, RANGE},
+};
+use size::Size;
+use std::{cmp::min, path::Path, process::ExitCode, sync::Arc, time::Duration};
 use tokio::{
     fs,
     io::{AsyncWriteExt, BufWriter},
     time::sleep,
 };
+use tracing::debug;
 
 #[derive(Parser)]
 #[command(
@@ -30,33 +42,127 @@ struct Args {
     /// specify the maximum number of re-download when failed.
     #[arg(short, long, default_value=None)]
     retry: Option<u32>,
+    /// Limit the download speed (e.g. 500K, 5M)
+    #[arg(long, value_parser = parse_size, value_name = "Rate")]
+    limit_rate: Option<u64>,
+    /// Change the rate limit by time of day (e.g. 01:00-07:00=unlimited,18:00-23:00=1M)
+    #[arg(long, value_parser = RateSchedule::parse, value_name = "Schedule")]
+    limit_schedule: Option<RateSchedule>,
+    /// Proxy for all requests, e.g. http://host:port or socks5h://host:port
+    #[arg(long, value_name = "Url")]
+    proxy: Option<String>,
+    /// Proxy for media files on data nodes only
+    #[arg(long, value_name = "Url")]
+    data_proxy: Option<String>,
+    /// Extra header sent with every request, e.g. "Referer: https://site/" (repeatable)
+    #[arg(long = "header", short = 'H', value_name = "Header")]
+    headers: Vec<String>,
+    /// User-Agent sent with every request
+    #[arg(long, value_name = "Text")]
+    user_agent: Option<String>,
+    /// Load cookies from a Netscape cookies.txt file
+    #[arg(long, value_name = "File")]
+    cookies: Option<String>,
+    /// Value of the `session` cookie of a logged in account
+    #[arg(long, value_name = "Value")]
+    session: Option<String>,
+    /// Seconds to wait for a connection, 0 disables the timeout
+    #[arg(long, default_value_t = CONNECT_TIMEOUT_SEC, value_name = "Seconds")]
+    connect_timeout: u64,
+    /// Seconds to wait for a response, 0 disables the timeout
+    #[arg(long, default_value_t = REQUEST_TIMEOUT_SEC, value_name = "Seconds")]
+    timeout: u64,
+    /// Retry a download when no bytes arrived for this many seconds, 0 disables the timeout
+    #[arg(long, default_value_t = STALL_TIMEOUT_SEC, value_name = "Seconds")]
+    stall_timeout: u64,
+    /// Config file [default: $XDG_CONFIG_HOME/cktool/config.toml]
+    #[arg(long, value_name = "File")]
+    config: Option<String>,
+    /// enable verbose logging, events down to the debug level are printed
+    #[arg(long, default_value_t = false)]
+    verbose: bool,
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> ExitCode {
     let args = Args::parse();
-    let mut urls = read_file(&args.file).await?;
+    let input = async {
+        if args.verbose {
+            LogOptions {
+                level: Some(VERBOSE_LEVEL.to_string()),
+                ..Default::default()
+            }
+            .init(std::io::stderr)?;
+        }
+        let urls = read_file(&args.file)
+            .await
+            .with_context(|| format!("Cannot read {}", args.file))?;
+        let config = Config::load(args.config.as_deref())?;
+        let mut options = RequestOptions {
+            proxy: args.proxy.clone(),
+            data_proxy: args.data_proxy.clone(),
+            user_agent: args.user_agent.clone(),
+            session: args.session.clone(),
+            ..Default::default()
+        };
+        options.set_timeouts(args.connect_timeout, args.timeout, args.stall_timeout);
+        for header in &args.headers {
+            options.add_header(header)?;
+        }
+        let options = options.with_config(&config)?;
+        if let Some(cookies) = &args.cookies {
+            options.load_cookies(cookies)?;
+        }
+        for url in urls.iter().filter(|url| !url.starts_with('#')) {
+            options.add_session_host(url);
+        }
+        anyhow::Ok((urls, options))
+    }
+    .await;
+    let (mut urls, options) = match input {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            return Exit::InvalidInput.into();
+        }
+    };
     let retry = args.retry.unwrap_or(100); // use for base value.
     let out = match args.out {
         Some(_as) => _as,
         None => ".".to_string(),
     };
+    let bandwidth = Bandwidth::new(args.limit_rate, args.limit_schedule.unwrap_or_default());
 
     println!("{}", "Parameters".green().bold());
     println!("{} {}", "File".blue().bold(), args.file);
     println!("{} {}", "retry".blue().bold(), retry);
     println!("{} {}", "out".blue().bold(), out);
+    if let Some(rate) = bandwidth.current_rate() {
+        println!(
+            "{} {}/s",
+            "limit-rate".blue().bold(),
+            Size::from_bytes(rate)
+        );
+    }
     println!();
 
     // create foder
-    if !Path::new(&out).is_dir() {
-        tokio::fs::create_dir_all(&out)
-            .await
-            .context("Failed create directory")?;
+    if !Path::new(&out).is_dir()
+        && let Err(err) = tokio::fs::create_dir_all(&out).await
+    {
+        eprintln!("Failed create directory: {}", err);
+        return Exit::Failure.into();
     }
 
+    // the first Ctrl+C stops after the running download is flushed.
+    let shutdown = Arc::new(Shutdown::new());
+    shutdown.listen(on_signal);
+    let (mut success, mut failed) = (0, 0);
     let url_len = urls.len();
     for i in 0..url_len {
+        if shutdown.is_requested() {
+            break;
+        }
         if urls[i].is_empty() {
             continue;
         }
@@ -66,16 +172,58 @@ async fn main() -> anyhow::Result<()> {
             println!("{} {}", "skip".yellow().bold(), urls[i].blue());
             continue;
         }
-        if download(&urls[i], retry, &out, i as u64).await.is_ok() {
-            urls[i] = format!("#{}", urls[i]);
-            fs::write(&args.file, urls.join("\n")).await?;
-        } else {
-            println!("{} {}", "Failed".red(), urls[i].red());
+        let result = download(
+            &urls[i], retry, &out, i as u64, &bandwidth, &options, &shutdown,
+        )
+        .await;
+        match result {
+            Ok(()) => {
+                success += 1;
+                urls[i] = format!("#{}", urls[i]);
+                if let Err(err) = fs::write(&args.file, urls.join("\n")).await {
+                    eprintln!("Cannot write {}: {}", args.file, err);
+                    return Exit::Failure.into();
+                }
+            }
+            Err(_) if shutdown.is_requested() => {
+                println!("{} {}", "Interrupted".yellow(), urls[i].yellow());
+            }
+            Err(err) => {
+                failed += 1;
+                println!("{} {}: {:#}", "Failed".red(), urls[i].red(), err);
+            }
         }
     }
+
+    if shutdown.is_requested() {
+        println!("{}", "Download was interrupted.".yellow().bold());
+        return Exit::Interrupted.into();
+    }
     println!("{}", "Done!".green());
+    if failed == 0 {
+        Exit::Success
+    } else if success == 0 {
+        Exit::Failure
+    } else {
+        Exit::Partial
+    }
+    .into()
+}
 
-    Ok(())
+/// Tell what Ctrl+C does, the second one aborts at once.
+fn on_signal(signal: Signal) {
+    match signal {
+        Signal::Stop => eprintln!(
+            "{}",
+            "Stopping after the running download is flushed, press Ctrl+C again to abort."
+                .yellow()
+                .bold()
+        ),
+        Signal::Abort => {
+            eprintln!("{}", "Aborted".red().bold());
+            std::process::exit(Exit::Interrupted.code());
+        }
+    }
 }
 
 /// Read file from file and convert to Vec<String> by split '\n'
@@ -86,7 +234,15 @@ pub async fn read_file(file: &str) -> anyhow::Result<Vec<String>> {
 }
 
 /// this func use to download each url.
-pub async fn download(url: &str, retry: u32, out: &str, index: u64) -> anyhow::Result<()> {
+pub async fn download(
+    url: &str,
+    retry: u32,
+    out: &str,
+    index: u64,
+    bandwidth: &Bandwidth,
+    options: &RequestOptions,
+    shutdown: &Shutdown,
+) -> anyhow::Result<()> {
     if let Some(file_name) = url.split("/").last() {
         let path_to_file = format!("{}/{}", out, file_name);
         let mut retry_request = retry;
@@ -100,27 +256,15 @@ pub async fn download(url: &str, retry: u32, out: &str, index: u64) -> anyhow::R
                     .await
                     .context("cannot get file size")?
                     .len();
-                let create_sender = request::new()?
+                let create_sender = request::new(options)?
                     .get(url)
                     .header(RANGE, format!("bytes={}-", file_size));
                 (create_sender, Some(file_size))
             } else {
-                (request::new()?.get(url), None)
+                (request::new(options)?.get(url), None)
             };
 
-            // create or open file follow by file_size.
-            let file = if file_size.is_some() {
-                tokio::fs::OpenOptions::new()
-                    .write(true)
-                    .append(true)
-                    .open(&path_to_file)
-                    .await?
-            } else {
-                tokio::fs::File::create(&path_to_file).await?
-            };
-            let mut file = BufWriter::new(file);
-
-            if let Ok(response) = sender.send().await {
+            if let Ok(response) = options.send_file(sender).await {
                 let total_size = match response.content_length().context("Cannot get total size") {
                     Ok(v) => v,
                     Err(_) => {
@@ -140,6 +284,10 @@ pub async fn download(url: &str, retry: u32, out: &str, index: u64) -> anyhow::R
                 }
                 // prevent too many requests
                 if StatusCode::TOO_MANY_REQUESTS == response.status() {
+                    if retry_request == 0 {
+                        return Err(anyhow!("Failed download"));
+                    }
+                    retry_request -= 1;
                     tokio::time::sleep(Duration::from_secs(TOO_MANY_REQUESTS_DELAY_SEC)).await;
                     continue;
                 }
@@ -154,6 +302,25 @@ pub async fn download(url: &str, retry: u32, out: &str, index: u64) -> anyhow::R
                     tokio::time::sleep(Duration::from_secs(ERROR_REQUEST_DELAY_SEC)).await;
                     continue;
                 }
+                // a server ignoring the range sends the whole file, it is written again.
+                let file_size = file_size.filter(|_| response.status() != StatusCode::OK);
+                let status = response.status().as_u16();
+                let content_type = response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                let file = if file_size.is_some() {
+                    tokio::fs::OpenOptions::new()
+                        .append(true)
+                        .open(&path_to_file)
+                        .await?
+                } else {
+                    tokio::fs::File::create(&path_to_file).await?
+                };
+                let mut file = BufWriter::new(file);
+
                 let mut downloaded: u64 = file_size.unwrap_or(0);
                 let p = create_progress_bar(total_size + downloaded);
                 p.set_message(format!(
@@ -164,32 +331,120 @@ pub async fn download(url: &str, retry: u32, out: &str, index: u64) -> anyhow::R
                 ));
 
                 let mut stream = response.bytes_stream();
+                // the first bytes of every body are checked against the file type,
+                // with the bytes already on disk for a resumed one.
+                let mut head = Some(match file_size {
+                    Some(_) => read_head(&path_to_file).await?,
+                    None => Vec::new(),
+                });
+                let mut invalid = None;
+                if let Some(buf) = head.take_if(|buf| buf.len() == SNIFF_BYTES) {
+                    invalid = check_magic(file_name, &buf).err();
+                }
 
-                #[allow(unused)]
-                'receive_item: while let Some(item) = stream.next().await {
-                    match item {
-                        Ok(item) => {
-                            let mut retry_write = 10;
-                            while (file.write_all(&item).await).is_err() && retry_write > 0 {
-                                retry_write -= 1;
+                while invalid.is_none() {
+                    let item = match options.next_chunk(&mut stream).await {
+                        Ok(Some(Ok(item))) => item,
+                        Ok(None) => break,
+                        // failed or stalled while downloading.
+                        chunk => {
+                            let _ = file.flush().await;
+                            if retry_request == 0 {
+                                p.finish_with_message(format!(
+                                    "[{}] {} {}",
+                                    index,
+                                    "Failed".red().bold(),
+                                    file_name
+                                ));
+                                return Err(anyhow!(if chunk.is_err() {
+                                    "Stalled"
+                                } else {
+                                    "Connection lost"
+                                }));
                             }
-
-                            let new = min(downloaded + (item.len() as u64), total_size);
-                            downloaded = new;
-                            p.set_position(new);
-                        }
-                        Err(_) => {
-                            // failed while downloading.
+                            retry_request -= 1;
                             p.set_message(format!(
                                 "[{}] {} {}",
                                 index,
-                                "Reconnect".yellow().bold(),
+                                if chunk.is_err() {
+                                    "Stalled, reconnect".yellow().bold()
+                                } else {
+                                    "Reconnect".yellow().bold()
+                                },
                                 file_name
                             ));
                             sleep(Duration::from_secs(1)).await;
                             continue 'request;
                         }
+                    };
+                    // stop at a chunk boundary, the next run resumes from the bytes on disk.
+                    if shutdown.is_requested() {
+                        let _ = file.flush().await;
+                        p.finish_with_message(format!(
+                            "[{}] {} {}",
+                            index,
+                            "Interrupted".yellow().bold(),
+                            file_name
+                        ));
+                        return Err(anyhow!("Interrupted"));
                     }
+                    bandwidth.consume(item.len()).await;
+                    let mut retry_write = 10;
+                    while let Err(err) = file.write_all(&item).await {
+                        if retry_write == 0 {
+                            p.finish_with_message(format!(
+                                "[{}] {} {}",
+                                index,
+                                "Failed".red().bold(),
+                                file_name
+                            ));
+                            return Err(err)
+                                .with_context(|| format!("Cannot write {}", path_to_file));
+                        }
+                        retry_write -= 1;
+                    }
+
+                    let new = min(downloaded + (item.len() as u64), total_size);
+                    downloaded = new;
+                    p.set_position(new);
+
+                    if let Some(buf) = &mut head {
+                        buf.extend_from_slice(&item[..min(item.len(), SNIFF_BYTES - buf.len())]);
+                        if buf.len() == SNIFF_BYTES {
+                            invalid = check_magic(file_name, buf).err();
+                            head = None;
+                            if invalid.is_some() {
+                                break;
+                            }
+                        }
+                    }
+                }
+                // a body shorter than `SNIFF_BYTES`.
+                if let Some(buf) = head {
+                    invalid = check_magic(file_name, &buf).err();
+                }
+                if let Some(reason) = invalid {
+                    let _ = file.flush().await;
+                    drop(file);
+                    let body = fs::read(&path_to_file).await.unwrap_or_default();
+                    debug!(
+                        target: DOWNLOAD,
+                        file = file_name,
+                        status,
+                        content_type,
+                        reason = %reason,
+                        body = %String::from_utf8_lossy(&body[..min(body.len(), LOG_BODY_LIMIT)]),
+                        "invalid content body"
+                    );
+                    let _ = fs::remove_file(&path_to_file).await;
+                    p.finish_with_message(format!(
+                        "[{}] {} {}: {}",
+                        index,
+                        "Invalid content".red().bold(),
+                        file_name,
+                        reason
+                    ));
+                    return Err(anyhow!("Invalid content"));
                 }
 
                 let _ = file.flush().await.context("file.flush");
@@ -0,0 +1,431 @@
+use anyhow::anyhow;
+
+use crate::config::Config;
+
+/// Built-in categories and their file extensions.
+const CATEGORIES: &[(&str, &[&str])] = &[
+    (
+        "image",
+        &[
+            "jpg", "jpeg", "png", "gif", "webp", "bmp", "tiff", "svg", "heic", "avif", "jfif",
+        ],
+    ),
+    (
+        "video",
+        &[
+            "mp4", "webm", "mkv", "avi", "mov", "flv", "wmv", "mpg", "mpeg", "m4v",
+        ],
+    ),
+    (
+        "audio",
+        &["mp3", "wav", "flac", "ogg", "m4a", "aac", "opus", "wma"],
+    ),
+    (
+        "archive",
+        &["zip", "rar", "7z", "tar", "gz", "bz2", "xz", "lzh"],
+    ),
+    (
+        "document",
+        &[
+            "pdf", "txt", "doc", "docx", "epub", "rtf", "odt", "md", "xls", "xlsx", "ppt", "pptx",
+        ],
+    ),
+    (
+        "project",
+        &[
+            "psd",
+            "clip",
+            "kra",
+            "sai",
+            "sai2",
+            "xcf",
+            "mdp",
+            "procreate",
+            "blend",
+        ],
+    ),
+];
+
+/// Category of files without a known extension.
+pub const OTHER: &str = "other";
+
+/// File categories by extension, the built-in ones and those of the config.
+#[derive(Clone, Debug)]
+pub struct Categories {
+    list: Vec<(String, Vec<String>)>,
+}
+
+impl Default for Categories {
+    fn default() -> Self {
+        Self {
+            list: CATEGORIES
+                .iter()
+                .map(|(name, exts)| {
+                    (
+                        name.to_string(),
+                        exts.iter().map(|ext| ext.to_string()).collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Categories {
+    /// Add the categories of the `[categories]` table of the config.
+    ///
+    /// e.g. `comic = ["cbz", "cbr"]`, a built-in name gets the given extensions
+    /// instead of its own. Extensions of the config win over the built-in ones.
+    pub fn with_config(mut self, config: &Config) -> Self {
+        let Some(table) = config.get_table("categories") else {
+            return self;
+        };
+        for (name, exts) in table {
+            let exts = match exts {
+                toml::Value::Array(exts) => exts
+                    .iter()
+                    .filter_map(|ext| ext.as_str().map(normalize_ext))
+                    .collect(),
+                toml::Value::String(ext) => vec![normalize_ext(ext)],
+                _ => continue,
+            };
+            self.list.retain(|(n, _)| n != name);
+            self.list.insert(0, (name.clone(), exts));
+        }
+        self
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.list.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        name == OTHER || self.list.iter().any(|(n, _)| n == name)
+    }
+
+    pub fn of_extension(&self, ext: &str) -> &str {
+        let ext = normalize_ext(ext);
+        self.list
+            .iter()
+            .find(|(_, exts)| exts.contains(&ext))
+            .map_or(OTHER, |(name, _)| name.as_str())
+    }
+
+    /// Category of a file name, `None` when it has no extension.
+    pub fn of_file(&self, fname: &str) -> Option<&str> {
+        extension(fname).map(|ext| self.of_extension(&ext))
+    }
+
+    /// Category from the `Content-Type` of a response, for files without extension.
+    pub fn of_content_type(&self, content_type: &str) -> &str {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        let name = match mime.as_str() {
+            "image/vnd.adobe.photoshop" | "application/x-photoshop" => "project",
+            "application/zip"
+            | "application/x-zip-compressed"
+            | "application/vnd.rar"
+            | "application/x-rar-compressed"
+            | "application/x-7z-compressed"
+            | "application/x-tar"
+            | "application/gzip" => "archive",
+            "application/pdf" | "application/epub+zip" | "application/msword" => "document",
+            m if m.starts_with("image/") => "image",
+            m if m.starts_with("video/") => "video",
+            m if m.starts_with("audio/") => "audio",
+            m if m.starts_with("text/") => "document",
+            _ => OTHER,
+        };
+        if self.contains(name) { name } else { OTHER }
+    }
+}
+
+/// Lowercase extension of a file name.
+pub fn extension(fname: &str) -> Option<String> {
+    std::path::Path::new(fname)
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(normalize_ext)
+}
+
+fn normalize_ext(ext: &str) -> String {
+    ext.trim().trim_start_matches('.').to_lowercase()
+}
+
+/// Result of a filter for a file name.
+pub enum FilterResult {
+    Keep,
+    Skip(String),
+    /// the file has no extension, its category comes from the `Content-Type`.
+    Unknown,
+}
+
+/// Select files by category and extension.
+///
+/// Excluded extensions and categories always win. When `only` or `ext` is
+/// given, a file has to match one of them.
+#[derive(Clone, Debug, Default)]
+pub struct FileFilter {
+    pub categories: Categories,
+    pub only: Vec<String>,
+    pub exclude: Vec<String>,
+    pub ext: Vec<String>,
+    pub exclude_ext: Vec<String>,
+}
+
+impl FileFilter {
+    pub fn new(
+        categories: Categories,
+        only: Vec<String>,
+        exclude: Vec<String>,
+        ext: Vec<String>,
+        exclude_ext: Vec<String>,
+    ) -> anyhow::Result<Self> {
+        for name in only.iter().chain(exclude.iter()) {
+            if !categories.contains(name) {
+                return Err(anyhow!(
+                    "Unknown category `{}`, categories: {}, {}",
+                    name,
+                    categories.names().join(", "),
+                    OTHER
+                ));
+            }
+        }
+        Ok(Self {
+            categories,
+            only,
+            exclude,
+            ext: ext.iter().map(|ext| normalize_ext(ext)).collect(),
+            exclude_ext: exclude_ext.iter().map(|ext| normalize_ext(ext)).collect(),
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        !(self.only.is_empty()
+            && self.exclude.is_empty()
+            && self.ext.is_empty()
+            && self.exclude_ext.is_empty())
+    }
+
+    pub fn check_name(&self, fname: &str) -> FilterResult {
+        match extension(fname) {
+            Some(ext) => match self.check(Some(&ext), self.categories.of_extension(&ext)) {
+                Some(reason) => FilterResult::Skip(reason),
+                None => FilterResult::Keep,
+            },
+            None if self.is_active() => FilterResult::Unknown,
+            None => FilterResult::Keep,
+        }
+    }
+
+    /// Reason to skip a file without extension, by the `Content-Type` of the response.
+    pub fn check_content_type(&self, content_type: Option<&str>) -> Option<String> {
+        let category = content_type.map_or(OTHER, |ct| self.categories.of_content_type(ct));
+        self.check(None, category)
+    }
+
+    /// Reason to skip a file, `None` when it is downloaded.
+    fn check(&self, ext: Option<&str>, category: &str) -> Option<String> {
+        if let Some(ext) = ext
+            && self.exclude_ext.iter().any(|e| e == ext)
+        {
+            return Some(format!("extension {} excluded", ext));
+        }
+        if self.exclude.iter().any(|c| c == category) {
+            return Some(format!("category {} excluded", category));
+        }
+        if self.only.is_empty() && self.ext.is_empty() {
+            return None;
+        }
+        if self.only.iter().any(|c| c == category)
+            || ext.is_some_and(|ext| self.ext.iter().any(|e| e == ext))
+        {
+            return None;
+        }
+        Some(format!("category {} not selected", category))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        downloader::{Downloader, DownloaderBuilder},
+        link::Link,
+    };
+
+    fn new_filter(
+        only: &[&str],
+        exclude: &[&str],
+        ext: &[&str],
+        exclude_ext: &[&str],
+    ) -> FileFilter {
+        let list = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        FileFilter::new(
+            Categories::default(),
+            list(only),
+            list(exclude),
+            list(ext),
+            list(exclude_ext),
+        )
+        .unwrap()
+    }
+
+    fn keeps(filter: &FileFilter, fname: &str) -> bool {
+        matches!(filter.check_name(fname), FilterResult::Keep)
+    }
+
+    fn builder() -> DownloaderBuilder {
+        Downloader::builder(Link::parse("https://kemono.cr/fanbox/user/1".to_string()).unwrap())
+            .outdir(std::env::temp_dir().to_str().unwrap())
+    }
+
+    #[test]
+    fn exclusions_win_over_selections() {
+        let filter = new_filter(&["video"], &[], &["zip"], &["webm"]);
+        assert!(keeps(&filter, "a.mp4"));
+        assert!(keeps(&filter, "a.zip"), "selected by extension");
+        assert!(
+            !keeps(&filter, "a.webm"),
+            "excluded extension of a selected category"
+        );
+        assert!(!keeps(&filter, "a.jpg"));
+
+        let filter = new_filter(&[], &["archive"], &["zip"], &[]);
+        assert!(
+            !keeps(&filter, "a.zip"),
+            "excluded category of a selected extension"
+        );
+        assert!(!keeps(&filter, "a.jpg"), "not selected by --ext");
+        assert!(keeps(&new_filter(&[], &["archive"], &[], &[]), "a.jpg"));
+    }
+
+    #[test]
+    fn extensions_ignore_case() {
+        let filter = new_filter(&[], &[], &[".MP4"], &["Jpg"]);
+        assert_eq!(filter.ext, ["mp4"]);
+        assert!(keeps(&filter, "a.Mp4"));
+        assert!(!keeps(&filter, "a.JPG"));
+        assert_eq!(Categories::default().of_file("A.PNG"), Some("image"));
+        assert_eq!(Categories::default().of_file("a.unknown"), Some(OTHER));
+    }
+
+    #[test]
+    fn files_without_extension() {
+        assert!(keeps(&FileFilter::default(), "download"));
+        assert_eq!(Categories::default().of_file("download"), None);
+        let filter = new_filter(&["image"], &[], &[], &[]);
+        assert!(matches!(
+            filter.check_name("download"),
+            FilterResult::Unknown
+        ));
+        assert_eq!(filter.check_content_type(Some("image/png")), None);
+        assert!(filter.check_content_type(Some("video/mp4")).is_some());
+        // no Content-Type is the other category.
+        assert!(filter.check_content_type(None).is_some());
+        let filter = new_filter(&[], &[], &[], &["zip"]);
+        assert_eq!(filter.check_content_type(Some("application/zip")), None);
+    }
+
+    #[test]
+    fn content_type_fallback() {
+        let categories = Categories::default();
+        assert_eq!(categories.of_content_type("video/mp4"), "video");
+        assert_eq!(
+            categories.of_content_type("Image/JPEG; charset=binary"),
+            "image"
+        );
+        assert_eq!(
+            categories.of_content_type("application/x-7z-compressed"),
+            "archive"
+        );
+        assert_eq!(
+            categories.of_content_type("image/vnd.adobe.photoshop"),
+            "project"
+        );
+        assert_eq!(categories.of_content_type("text/plain"), "document");
+        assert_eq!(
+            categories.of_content_type("application/octet-stream"),
+            OTHER
+        );
+        assert_eq!(categories.of_content_type(""), OTHER);
+    }
+
+    #[test]
+    fn config_categories() {
+        let table = r#"
+            [categories]
+            comic = ["CBZ", ".cbr"]
+            image = "png"
+        "#
+        .parse::<toml::Table>()
+        .unwrap();
+        let config = Config::default().with_section(&table);
+        let categories = Categories::default().with_config(&config);
+        assert_eq!(categories.of_extension("cbr"), "comic");
+        assert_eq!(categories.of_extension("png"), "image");
+        // a built-in name gets the given extensions instead of its own.
+        assert_eq!(categories.of_extension("jpg"), OTHER);
+        assert!(
+            FileFilter::new(
+                categories,
+                vec!["comic".to_string()],
+                vec![],
+                vec![],
+                vec![]
+            )
+            .is_ok()
+        );
+        assert!(
+            FileFilter::new(
+                Categories::default(),
+                vec!["comic".to_string()],
+                vec![],
+                vec![],
+                vec![]
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn video_and_image_only_are_added_to_only() {
+        let downloader = builder()
+            .video_only(true)
+            .image_only(true)
+            .filter(new_filter(&["video", "audio"], &[], &[], &[]))
+            .build();
+        assert_eq!(downloader.filter.only, ["video", "audio", "image"]);
+        assert!(downloader.name_filter("a.mp3").is_none());
+        assert!(downloader.name_filter("a.zip").is_some());
+        assert!(builder().build().name_filter("a.zip").is_none());
+    }
+
+    #[tokio::test]
+    async fn size_bounds_keep_unknown_sizes() {
+        let downloader = builder().size_range(Some(10), Some(100)).build();
+        assert!(downloader.size_filter(9).is_some());
+        assert!(downloader.size_filter(10).is_none());
+        assert!(downloader.size_filter(100).is_none());
+        assert!(downloader.size_filter(101).is_some());
+
+        let urls = ["small.jpg", "large.jpg", "fits.jpg", "unknown.jpg"]
+            .map(|name| format!("https://n1.kemono.cr/data/{}", name));
+        {
+            let mut manifest = downloader.manifest.lock().await;
+            manifest.add_post("1");
+            manifest.set_files("1", &urls);
+            for (url, size) in urls.iter().zip([Some(1), Some(1000), Some(50), None]) {
+                manifest.set_size("1", url, size);
+            }
+        }
+        let estimate = downloader.summarize().await;
+        assert_eq!(estimate.categories[0].files, 2, "fits.jpg and unknown.jpg");
+        assert_eq!(estimate.size(), 50);
+        assert_eq!(estimate.unknown, 1);
+    }
+}
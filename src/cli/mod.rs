@@ -0,0 +1,7 @@
+// private
+mod print;
+mod progress;
+
+// public
+pub use print::{on_signal, print_dry_run, print_estimate, print_parameters, print_reports};
+pub use progress::{Progress, ProgressWriter};
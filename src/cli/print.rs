@@ -0,0 +1,262 @@
+use cktool::{
+    declare::Exit,
+    downloader::{Downloader, DryRun, Estimate},
+    utils::Signal,
+};
+use colored::Colorize;
+use reqwest::header::{AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION};
+use size::Size;
+
+/// Parameters of the run, printed before it starts.
+pub fn print_parameters(downloader: &Downloader) {
+    println!("{}", "Parameters".green().bold());
+    println!("{} {}", "Link".blue().bold(), downloader.link.url());
+    println!("{} {}", "Outdir".blue().bold(), downloader.outdir);
+    println!(
+        "{} {}{}",
+        "TaskLimit".blue().bold(),
+        downloader.task_limit,
+        if downloader.concurrency.is_adaptive() {
+            " (adaptive)"
+        } else {
+            ""
+        }
+    );
+    println!("{} {}", "Retry".blue().bold(), downloader.retry);
+    println!("{} {}", "VideoOnly".blue().bold(), downloader.video_only);
+    println!("{} {}", "ImageOnly".blue().bold(), downloader.image_only);
+    if !downloader.filter.only.is_empty() {
+        println!(
+            "{} {}",
+            "Only".blue().bold(),
+            downloader.filter.only.join(",")
+        );
+    }
+    if !downloader.filter.exclude.is_empty() {
+        println!(
+            "{} {}",
+            "Exclude".blue().bold(),
+            downloader.filter.exclude.join(",")
+        );
+    }
+    if !downloader.filter.ext.is_empty() {
+        println!(
+            "{} {}",
+            "Ext".blue().bold(),
+            downloader.filter.ext.join(",")
+        );
+    }
+    if !downloader.filter.exclude_ext.is_empty() {
+        println!(
+            "{} {}",
+            "ExcludeExt".blue().bold(),
+            downloader.filter.exclude_ext.join(",")
+        );
+    }
+    if let Some(min_size) = downloader.min_size {
+        println!("{} {}", "MinSize".blue().bold(), Size::from_bytes(min_size));
+    }
+    if let Some(max_size) = downloader.max_size {
+        println!("{} {}", "MaxSize".blue().bold(), Size::from_bytes(max_size));
+    }
+    if let Some(since) = downloader.since {
+        println!("{} {}", "Since".blue().bold(), since);
+    }
+    println!("{} {}", "Verbose".blue().bold(), downloader.verbose);
+    println!(
+        "{} {} above {}",
+        "Segments".blue().bold(),
+        downloader.segments,
+        Size::from_bytes(downloader.segment_threshold)
+    );
+    println!(
+        "{} {}",
+        "Connections".blue().bold(),
+        downloader.connections.available_permits()
+    );
+    println!(
+        "{} {}",
+        "ApiRps".blue().bold(),
+        downloader.api_limiter.max_rate()
+    );
+    println!("{} {}", "ApiTask".blue().bold(), downloader.api_task);
+    if let Some(proxy) = &downloader.request.proxy {
+        println!("{} {}", "Proxy".blue().bold(), proxy);
+    }
+    if let Some(proxy) = &downloader.request.api_proxy {
+        println!("{} {}", "ApiProxy".blue().bold(), proxy);
+    }
+    if let Some(proxy) = &downloader.request.data_proxy {
+        println!("{} {}", "DataProxy".blue().bold(), proxy);
+    }
+    let secs = |t: Option<std::time::Duration>| {
+        t.map_or("none".to_string(), |t| format!("{}s", t.as_secs()))
+    };
+    println!(
+        "{} connect {}, request {}, stall {}",
+        "Timeouts".blue().bold(),
+        secs(downloader.request.connect_timeout),
+        secs(downloader.request.timeout),
+        secs(downloader.request.stall_timeout)
+    );
+    if let Some(user_agent) = &downloader.request.user_agent {
+        println!("{} {}", "UserAgent".blue().bold(), user_agent);
+    }
+    for (name, value) in &downloader.request.headers {
+        // credentials are only shown as set, like the session.
+        let value = if [COOKIE, AUTHORIZATION, PROXY_AUTHORIZATION].contains(name) {
+            "set"
+        } else {
+            value.to_str().unwrap_or_default()
+        };
+        println!("{} {}: {}", "Header".blue().bold(), name, value);
+    }
+    if downloader.request.session.is_some() {
+        println!("{} set", "Session".blue().bold());
+    }
+    if downloader.force {
+        println!("{} {}", "Force".blue().bold(), downloader.force);
+    }
+    if let Some(rate) = downloader.bandwidth.current_rate() {
+        println!("{} {}/s", "LimitRate".blue().bold(), Size::from_bytes(rate));
+    }
+    println!();
+}
+
+/// Failed and skipped files, then the totals of the run.
+pub async fn print_reports(downloader: &Downloader) {
+    let info = downloader.info.lock().await;
+
+    let failed_post = info.get_failed_post();
+    for post in &failed_post {
+        println!(" {}\t{}", "Failed post".red(), post.red());
+    }
+
+    let failed_file = info.get_failed_file();
+    let failed_file_len = failed_file.len();
+    if !failed_file.is_empty() {
+        for file in failed_file {
+            println!(" {}\t{}", "Failed".red(), file.replace("api/v1/", "").red());
+        }
+    }
+
+    let skip_file = info.get_skip_file();
+    let skip_file_len = skip_file.len();
+    if !skip_file.is_empty() {
+        for (file, reason) in skip_file {
+            println!(
+                " {}\t{} ({})",
+                "Skip".yellow(),
+                file.replace("api/v1/", "").yellow(),
+                reason
+            );
+        }
+    }
+
+    let interrupted_file_len = info.get_interrupted_file().len();
+    match info.exit(downloader.shutdown.is_requested()) {
+        Exit::Success => println!("Download success  to {} folder.", downloader.outdir.blue()),
+        Exit::Partial => println!(
+            "{} to {} folder.",
+            "Download finished with failures".yellow().bold(),
+            downloader.outdir.blue()
+        ),
+        Exit::Interrupted => println!("{}", "Download was interrupted.".yellow().bold()),
+        Exit::Failure | Exit::InvalidInput if info.get_error().is_some() => {
+            println!("{}", "Download stopped on an error.".red().bold())
+        }
+        Exit::Failure | Exit::InvalidInput => {
+            println!("{}", "Nothing could be downloaded.".red().bold())
+        }
+    }
+    let file_size = Size::from_bytes(info.get_file_size());
+    println!("{}: {}", "Total size".blue(), file_size);
+    println!("{}: {}", "success files".green(), info.get_success_file());
+    println!("{}: {}", "Skipped files".yellow(), skip_file_len);
+    println!("{}: {}", "Failed files".red(), failed_file_len);
+    if !failed_post.is_empty() {
+        println!("{}: {}", "Failed posts".red(), failed_post.len());
+    }
+    if interrupted_file_len > 0 {
+        println!("{}: {}", "Interrupted files".yellow(), interrupted_file_len);
+    }
+}
+
+pub fn print_estimate(estimate: &Estimate) {
+    println!("{}", "Estimate".green().bold());
+    for category in &estimate.categories {
+        println!(
+            "{} {} files, {}, {} left",
+            category.name.blue().bold(),
+            category.files,
+            Size::from_bytes(category.size),
+            Size::from_bytes(category.remaining)
+        );
+    }
+    println!(
+        "{} {}, {} left",
+        "Total".blue().bold(),
+        Size::from_bytes(estimate.size()),
+        Size::from_bytes(estimate.remaining())
+    );
+    if estimate.unknown > 0 {
+        println!(
+            "{} {} files",
+            "UnknownSize".yellow().bold(),
+            estimate.unknown
+        );
+    }
+    if let Some(available) = estimate.available {
+        let free = format!(
+            "{} {}",
+            "FreeSpace".blue().bold(),
+            Size::from_bytes(available)
+        );
+        if estimate.remaining() > available {
+            println!("{} {}", free, "not enough".red().bold());
+        } else {
+            println!("{}", free);
+        }
+    }
+}
+
+pub fn print_dry_run(dry_run: &DryRun) {
+    for file in &dry_run.files {
+        println!(
+            "{}\t{}\t{}\t{}",
+            file.post,
+            file.category.blue(),
+            file.size
+                .map_or("?".to_string(), |size| Size::from_bytes(size).to_string()),
+            file.path.purple()
+        );
+    }
+    for (url, reason) in &dry_run.skipped {
+        println!(" {}\t{} ({})", "Skip".yellow(), url.yellow(), reason);
+    }
+    let size = dry_run.files.iter().filter_map(|f| f.size).sum::<u64>();
+    let unknown = dry_run.files.iter().filter(|f| f.size.is_none()).count();
+    println!("{}: {}", "Files".green(), dry_run.files.len());
+    println!("{}: {}", "Total size".blue(), Size::from_bytes(size));
+    if unknown > 0 {
+        println!("{}: {}", "Unknown size".yellow(), unknown);
+    }
+    println!("{}: {}", "Skipped files".yellow(), dry_run.skipped.len());
+    println!("{}: {}", "Already downloaded".green(), dry_run.done);
+}
+
+/// Tell what Ctrl+C does, the second one aborts at once.
+pub fn on_signal(signal: Signal) {
+    match signal {
+        Signal::Stop => eprintln!(
+            "{}",
+            "Stopping after running downloads are flushed, press Ctrl+C again to abort."
+                .yellow()
+                .bold()
+        ),
+        Signal::Abort => {
+            eprintln!("{}", "Aborted".red().bold());
+            std::process::exit(Exit::Interrupted.code());
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use cktool::downloader::{Event, StatusBar, Wait};
+use colored::Colorize;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use size::Size;
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+use tokio::time::sleep;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Progress bars of every downloader, log lines on the terminal are printed above them.
+fn multi_progress() -> MultiProgress {
+    static PROGRESS: OnceLock<MultiProgress> = OnceLock::new();
+    PROGRESS.get_or_init(MultiProgress::new).clone()
+}
+
+/// Writes log lines to stderr with the progress bars hidden.
+pub struct ProgressWriter;
+
+impl Write for ProgressWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        multi_progress().suspend(|| io::stderr().write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for ProgressWriter {
+    type Writer = ProgressWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        ProgressWriter
+    }
+}
+
+/// Bar of a file being downloaded.
+struct FileBar {
+    bar: ProgressBar,
+    status: StatusBar,
+    /// message shown while bytes arrive, restored after a wait.
+    message: String,
+    waiting: bool,
+    /// `FileStarted` was received, a bar of a file waiting before its first
+    /// answer shows no bytes yet.
+    started: bool,
+    /// bytes already on disk when the first request was answered.
+    resumed: u64,
+}
+
+/// Fields of `Event::FileStarted` needed to draw the bar of a file.
+struct Started<'a> {
+    url: &'a str,
+    name: &'a str,
+    size: u64,
+    downloaded: u64,
+    attempt: u64,
+    segments: usize,
+    status: &'a StatusBar,
+}
+
+/// Draws the events of a run as progress bars and lines above them.
+pub struct Progress {
+    multi: MultiProgress,
+    bars: Mutex<HashMap<String, FileBar>>,
+    /// bars showing their final message, cleared at the latest when the run ends.
+    finishing: Mutex<Vec<ProgressBar>>,
+    adaptive: Mutex<Option<ProgressBar>>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            multi: multi_progress(),
+            bars: Mutex::new(HashMap::new()),
+            finishing: Mutex::new(Vec::new()),
+            adaptive: Mutex::new(None),
+        }
+    }
+}
+
+fn prefix(status: &StatusBar) -> String {
+    format!("[{}/{}]", status.total, status.queues)
+}
+
+impl Progress {
+    /// Show the final message of a bar for a moment, the worker does not wait for it.
+    fn finish_later(&self, bar: ProgressBar, message: String, delay: Duration) {
+        bar.finish_with_message(message);
+        self.finishing.lock().unwrap().push(bar.clone());
+        tokio::spawn(async move {
+            sleep(delay).await;
+            bar.finish_and_clear();
+        });
+    }
+
+    /// Print a line above the progress bars, the workers may already be drawing.
+    fn println(&self, line: String) {
+        self.multi.suspend(|| println!("{}", line));
+    }
+
+    fn eprintln(&self, line: String) {
+        self.multi.suspend(|| eprintln!("{}", line));
+    }
+
+    pub fn handle(&self, event: &Event) {
+        match event {
+            Event::Resumed { posts } => {
+                self.println(format!("Continue from manifest, {} posts left", posts))
+            }
+            Event::FetchingPages => self.println("Start fetching pages".to_string()),
+            Event::ProfileFetched { url, posts } => self.println(format!(
+                "fetching {} -- {} {} posts",
+                url.purple(),
+                "PASS".green().bold(),
+                posts
+            )),
+            Event::ProfileUnavailable { url, status } => self.println(format!(
+                "fetching {} -- {} {}",
+                url.purple(),
+                "NONE".yellow(),
+                status
+            )),
+            Event::PageFetched { url, .. } => self.println(format!(
+                "fetching {} -- {}",
+                url.purple(),
+                "PASS".green().bold()
+            )),
+            Event::PageRetry { url, status } => self.println(format!(
+                "fetching {} -- {}",
+                url.purple(),
+                match status {
+                    Some(status) => format!("{} {}", "RETRY".yellow(), status),
+                    None => "RETRY".yellow().to_string(),
+                }
+            )),
+            Event::PageFailed { url } => self.println(format!(
+                "fetching {} -- {}",
+                url.purple(),
+                "FAILED".red().bold()
+            )),
+            Event::ResolvingPosts { posts } => {
+                self.println(format!("Resolving files of {} posts", posts))
+            }
+            Event::FetchingSizes { files } => {
+                self.println(format!("Fetching size of {} files", files))
+            }
+            Event::PostResolved { .. } | Event::PostFailed { .. } => {}
+            // a file may be skipped after waiting for its first answer.
+            Event::FileSkipped { url, .. } | Event::FileInterrupted { url, .. } => {
+                if let Some(file) = self.bars.lock().unwrap().remove(url) {
+                    file.bar.finish_and_clear();
+                }
+            }
+            Event::FileStarted {
+                url,
+                name,
+                size,
+                downloaded,
+                attempt,
+                segments,
+                status,
+            } => self.started(Started {
+                url,
+                name,
+                size: *size,
+                downloaded: *downloaded,
+                attempt: *attempt,
+                segments: *segments,
+                status,
+            }),
+            Event::FileProgress { url, bytes } => {
+                if let Some(file) = self.bars.lock().unwrap().get_mut(url) {
+                    file.bar.inc(*bytes);
+                    if file.waiting {
+                        file.bar.set_message(file.message.clone());
+                        file.waiting = false;
+                    }
+                }
+            }
+            Event::FileWaiting {
+                url,
+                name,
+                wait,
+                status,
+            } => self.waiting(url, name, wait, status),
+            Event::FileFinished { url, name, .. } => {
+                let Some(file) = self.bars.lock().unwrap().remove(url) else {
+                    return;
+                };
+                let (state, delay) = if file.bar.position() == file.resumed {
+                    ("was done...".green().bold(), Duration::from_millis(500))
+                } else {
+                    ("success".green().bold(), Duration::from_secs(1))
+                };
+                let message = format!("{} {} {}", prefix(&file.status), name.purple(), state);
+                self.finish_later(file.bar, message, delay);
+            }
+            Event::FileFailed {
+                url, name, reason, ..
+            } => {
+                self.eprintln(format!("{} {}: {}", "Failed".red().bold(), name, reason));
+                if let Some(file) = self.bars.lock().unwrap().remove(url) {
+                    let message = format!(
+                        "{} {} {}",
+                        prefix(&file.status),
+                        name.purple(),
+                        "Failed".red().bold()
+                    );
+                    self.finish_later(file.bar, message, Duration::from_secs(1));
+                }
+            }
+            Event::Concurrency {
+                level,
+                max,
+                throughput,
+            } => {
+                let mut adaptive = self.adaptive.lock().unwrap();
+                let bar =
+                    adaptive.get_or_insert_with(|| self.multi.add(ProgressBar::new_spinner()));
+                bar.set_message(format!(
+                    "{} {}/{} {}/s",
+                    "Adaptive tasks".blue().bold(),
+                    level.to_string().yellow(),
+                    max,
+                    Size::from_bytes(*throughput)
+                ));
+            }
+            Event::Stopped { reason } => self.eprintln(reason.red().bold().to_string()),
+            Event::Finished { .. } => {
+                if let Some(bar) = self.adaptive.lock().unwrap().take() {
+                    bar.finish_and_clear();
+                }
+                for (_, file) in self.bars.lock().unwrap().drain() {
+                    file.bar.finish_and_clear();
+                }
+                for bar in self.finishing.lock().unwrap().drain(..) {
+                    bar.finish_and_clear();
+                }
+            }
+        }
+    }
+
+    fn started(&self, started: Started) {
+        let Started {
+            url,
+            name,
+            size,
+            downloaded,
+            attempt,
+            segments,
+            status,
+        } = started;
+        let counter = if segments > 1 {
+            format!("[x{}]", segments)
+        } else if attempt > 1 {
+            format!("[{}]", attempt)
+        } else {
+            String::new()
+        };
+        let message = format!(
+            "{} {}{} {}",
+            prefix(status),
+            counter.yellow(),
+            name.purple(),
+            "downloading...".blue().bold()
+        );
+        let mut bars = self.bars.lock().unwrap();
+        let file = bars
+            .entry(url.to_string())
+            .or_insert_with(|| self.file_bar(size, status));
+        if !file.started {
+            file.started = true;
+            file.resumed = downloaded;
+        }
+        file.bar.set_length(size);
+        file.bar.set_position(downloaded);
+        file.bar.set_message(message.clone());
+        file.message = message;
+        file.waiting = false;
+    }
+
+    fn file_bar(&self, size: u64, status: &StatusBar) -> FileBar {
+        let bar = self.multi.add(ProgressBar::new(size));
+        bar.set_style(ProgressStyle::default_bar()
+            .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
+            .unwrap()
+            .progress_chars("#>-"));
+        FileBar {
+            bar,
+            status: status.clone(),
+            message: String::new(),
+            waiting: false,
+            started: false,
+            resumed: 0,
+        }
+    }
+
+    /// The first answers of a file may already be 429 or an error, its bar
+    /// is shown before `FileStarted` then.
+    fn waiting(&self, url: &str, name: &str, wait: &Wait, status: &StatusBar) {
+        let mut bars = self.bars.lock().unwrap();
+        let file = bars
+            .entry(url.to_string())
+            .or_insert_with(|| self.file_bar(0, status));
+        let prefix = prefix(&file.status);
+        let message = match wait {
+            Wait::TooManyRequests { secs } => format!(
+                "{} {} {} {} secs.",
+                prefix,
+                name.purple(),
+                "wait".yellow().bold(),
+                secs.to_string().yellow()
+            ),
+            Wait::Retry { attempt, secs, .. } => format!(
+                "{} {} {}[{}] {} {} secs.",
+                prefix,
+                name.purple(),
+                "retry".blue().bold(),
+                attempt,
+                "wait".yellow().bold(),
+                secs
+            ),
+            Wait::Stalled => format!(
+                "{} {} {}",
+                prefix,
+                name.purple(),
+                "Stalled, reconnect...".yellow().bold()
+            ),
+            Wait::Reconnect => format!(
+                "{} {} {}",
+                prefix,
+                name.purple(),
+                "Reconnect...".yellow().bold()
+            ),
+        };
+        file.bar.set_message(message);
+        file.waiting = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status() -> StatusBar {
+        StatusBar {
+            post: "1".to_string(),
+            queues: 2,
+            total: 3,
+        }
+    }
+
+    #[test]
+    fn wait_before_the_file_starts() {
+        let progress = Progress::default();
+        let url = "https://n1.kemono.cr/data/a.mp4";
+        progress.handle(&Event::FileWaiting {
+            url: url.to_string(),
+            name: "a.mp4".to_string(),
+            wait: Wait::TooManyRequests { secs: 2 },
+            status: status(),
+        });
+        {
+            let bars = progress.bars.lock().unwrap();
+            let file = bars.get(url).expect("bar of a waiting file");
+            assert!(file.waiting && !file.started);
+            assert!(
+                file.bar.message().contains("wait"),
+                "{}",
+                file.bar.message()
+            );
+        }
+
+        progress.handle(&Event::FileStarted {
+            url: url.to_string(),
+            name: "a.mp4".to_string(),
+            size: 100,
+            downloaded: 40,
+            attempt: 2,
+            segments: 1,
+            status: status(),
+        });
+        let bars = progress.bars.lock().unwrap();
+        let file = &bars[url];
+        assert!(file.started && !file.waiting);
+        assert_eq!(file.resumed, 40);
+        assert_eq!(file.bar.length(), Some(100));
+        assert!(file.bar.message().contains("downloading"));
+    }
+
+    #[test]
+    fn skipped_file_clears_its_bar() {
+        let progress = Progress::default();
+        let url = "https://n1.kemono.cr/data/a.zip";
+        progress.handle(&Event::FileWaiting {
+            url: url.to_string(),
+            name: "a.zip".to_string(),
+            wait: Wait::Retry {
+                status: 502,
+                attempt: 1,
+                secs: 2,
+            },
+            status: status(),
+        });
+        progress.handle(&Event::FileSkipped {
+            post: "1".to_string(),
+            url: url.to_string(),
+            name: "a.zip".to_string(),
+            reason: "larger than 1 MB".to_string(),
+        });
+        assert!(progress.bars.lock().unwrap().is_empty());
+    }
+}
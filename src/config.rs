@@ -0,0 +1,417 @@
+use anyhow::{Context, Result, bail};
+use clap::{Arg, ArgMatches, Command, parser::ValueSource};
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+use url::Url;
+
+use crate::link::creator_id;
+
+/// Options which cannot be set by the config file.
+const COMMAND_LINE_ONLY: &[&str] = &["config", "profile", "completion", "help", "version"];
+
+/// Tables of the config which are not options.
+const SECTIONS: &[&str] = &["profiles", "sites", "creators", "categories"];
+
+/// Options which select the same files, one of them given on the command line
+/// drops the others from the config.
+const SAME_SELECTION: &[&[&str]] = &[&["video-only", "image-only", "only"]];
+
+/// Settings read from the TOML config file.
+///
+/// Keys use the same names as the long command line options, e.g.
+/// `proxy = "socks5://127.0.0.1:9050"`. Command line options take precedence.
+///
+/// Named profiles, sites and creators override the top-level keys:
+///
+/// ```toml
+/// task = 4
+///
+/// [profiles.nas]
+/// out = "/mnt/nas/cktool"
+///
+/// [sites."coomer.st"]
+/// proxy = "socks5h://127.0.0.1:9050"
+///
+/// [creators."fanbox/12345"]
+/// only = ["image"]
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    table: Table,
+    /// file the settings were read from.
+    path: Option<PathBuf>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/cktool/config.toml` or the platform equivalent.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("cktool").join("config.toml"))
+    }
+
+    /// Load the config file, a missing default file gives an empty config.
+    ///
+    /// # Arguments
+    /// * `path` - file given by `--config`, it has to exist.
+    pub fn load(path: Option<&str>) -> Result<Self> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+        Self::from_file(&path)
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read config file {}", path.display()))?;
+        let table = content
+            .parse::<Table>()
+            .with_context(|| format!("Cannot parse config file {}", path.display()))?;
+        Ok(Self {
+            table,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The config in messages, with the path of its file.
+    pub fn source(&self) -> String {
+        match &self.path {
+            Some(path) => format!("config file {}", path.display()),
+            None => "config file".to_string(),
+        }
+    }
+
+    /// Settings of a run, later sections win over the earlier ones.
+    ///
+    /// Top-level keys, then `[profiles.<profile>]`, `[sites."<host>"]` and
+    /// `[creators."<service>/<id>"]` of `url`.
+    pub fn resolve(&self, profile: Option<&str>, url: Option<&str>) -> Result<Self> {
+        let mut sections = Vec::new();
+        if let Some(profile) = profile {
+            let section = self
+                .section("profiles", profile)
+                .with_context(|| format!("Unknown profile `{}` in config file", profile))?;
+            sections.push(section);
+        }
+        if let Some(url) = url.and_then(|url| Url::parse(url).ok()) {
+            if let Some(host) = url.host_str()
+                && let Some(site) = self.get_table("sites").and_then(|sites| {
+                    sites
+                        .iter()
+                        .find(|(name, _)| {
+                            host == name.as_str() || host.ends_with(&format!(".{}", name))
+                        })
+                        .and_then(|(_, section)| section.as_table())
+                })
+            {
+                sections.push(site);
+            }
+            if let Some(section) = creator_id(url.as_str())
+                .and_then(|(service, id)| self.section("creators", &format!("{}/{}", service, id)))
+            {
+                sections.push(section);
+            }
+        }
+        Ok(sections
+            .into_iter()
+            .fold(self.clone(), |config, section| config.with_section(section)))
+    }
+
+    /// Options of `section` win over the ones of this config.
+    pub fn with_section(mut self, section: &Table) -> Self {
+        for (key, value) in section {
+            self.table.insert(key.clone(), value.clone());
+        }
+        self
+    }
+
+    fn section(&self, kind: &str, name: &str) -> Option<&Table> {
+        self.get_table(kind)?.get(name)?.as_table()
+    }
+
+    /// Command line arguments for the options of `command` not given on the command line.
+    ///
+    /// A flag is given by `true`, a list gives the option once for each value.
+    /// Options conflicting with one given on the command line are left out.
+    /// A key which is neither an option of `command` or of one of its
+    /// subcommands nor a section is an error.
+    pub fn to_args(&self, command: &Command, matches: &ArgMatches) -> Result<Vec<String>> {
+        let options = command
+            .get_arguments()
+            .chain(command.get_subcommands().flat_map(Command::get_arguments))
+            .filter_map(Arg::get_long)
+            .collect::<Vec<_>>();
+        if let Some(key) = self
+            .table
+            .keys()
+            .find(|key| !SECTIONS.contains(&key.as_str()) && !options.contains(&key.as_str()))
+        {
+            bail!("Unknown key `{}` in the {}", key, self.source());
+        }
+        Ok(self.args_of(command, matches))
+    }
+
+    /// Command line arguments for the options of the subcommand of `matches`,
+    /// they go after its name.
+    pub fn subcommand_args(&self, command: &Command, matches: &ArgMatches) -> Vec<String> {
+        match matches
+            .subcommand()
+            .and_then(|(name, matches)| Some((command.find_subcommand(name)?, matches)))
+        {
+            Some((subcommand, matches)) => self.args_of(subcommand, matches),
+            None => Vec::new(),
+        }
+    }
+
+    fn args_of(&self, command: &Command, matches: &ArgMatches) -> Vec<String> {
+        let given = |arg: &Arg| {
+            matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+        };
+        let given_long = |long: &str| {
+            command
+                .get_arguments()
+                .any(|arg| arg.get_long() == Some(long) && given(arg))
+        };
+        let mut args = Vec::new();
+        for arg in command.get_arguments() {
+            let Some(long) = arg.get_long() else {
+                continue;
+            };
+            let overridden = command.get_arg_conflicts_with(arg).into_iter().any(given)
+                || SAME_SELECTION
+                    .iter()
+                    .filter(|group| group.contains(&long))
+                    .any(|group| group.iter().any(|other| given_long(other)));
+            if COMMAND_LINE_ONLY.contains(&long) || given(arg) || overridden {
+                continue;
+            }
+            let values = match self.table.get(long) {
+                Some(Value::Boolean(true)) => {
+                    args.push(format!("--{}", long));
+                    continue;
+                }
+                Some(Value::Array(values)) => values.iter().filter_map(scalar).collect(),
+                Some(value) => scalar(value).into_iter().collect::<Vec<_>>(),
+                None => continue,
+            };
+            for value in values {
+                args.push(format!("--{}={}", long, value));
+            }
+        }
+        args
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<String> {
+        self.table.get(key)?.as_str().map(str::to_string)
+    }
+
+    pub fn get_table(&self, key: &str) -> Option<&Table> {
+        self.table.get(key)?.as_table()
+    }
+
+    /// A list of strings, a single string is a list of one.
+    pub fn get_list(&self, key: &str) -> Vec<String> {
+        match self.table.get(key) {
+            Some(toml::Value::Array(values)) => values
+                .iter()
+                .filter_map(|v| v.as_str().map(str::to_string))
+                .collect(),
+            Some(toml::Value::String(value)) => vec![value.clone()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Integer(value) => Some(value.to_string()),
+        Value::Float(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ArgAction;
+
+    fn config(content: &str) -> Config {
+        Config {
+            table: content.parse().unwrap(),
+            path: None,
+        }
+    }
+
+    fn command() -> Command {
+        Command::new("cktool")
+            .arg(Arg::new("task").long("task"))
+            .arg(Arg::new("out").long("out"))
+            .arg(Arg::new("profile").long("profile"))
+            .arg(Arg::new("proxy").long("proxy"))
+            .arg(Arg::new("only").long("only").action(ArgAction::Append))
+            .arg(
+                Arg::new("video_only")
+                    .long("video-only")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("image_only")
+                    .long("image-only")
+                    .action(ArgAction::SetTrue),
+            )
+            .subcommand(
+                Command::new("watch")
+                    .arg(Arg::new("interval").long("interval"))
+                    .arg(Arg::new("jitter").long("jitter")),
+            )
+    }
+
+    fn to_args(config: &Config, argv: &[&str]) -> Vec<String> {
+        let command = command();
+        let matches = command
+            .clone()
+            .try_get_matches_from(std::iter::once("cktool").chain(argv.iter().copied()))
+            .unwrap();
+        config.to_args(&command, &matches).unwrap()
+    }
+
+    const CONFIG: &str = r#"
+        task = 1
+        out = "top"
+        proxy = "top"
+
+        [profiles.nas]
+        task = 2
+        out = "/mnt/nas"
+
+        [sites."kemono.cr"]
+        task = 3
+        proxy = "socks5h://127.0.0.1:9050"
+
+        [creators."patreon/123"]
+        task = 4
+    "#;
+
+    #[test]
+    fn creator_wins_over_site_and_profile() {
+        let config = config(CONFIG)
+            .resolve(Some("nas"), Some("https://kemono.cr/patreon/user/123"))
+            .unwrap();
+        assert_eq!(config.table.get("task"), Some(&Value::Integer(4)));
+        assert_eq!(config.get_str("proxy").unwrap(), "socks5h://127.0.0.1:9050");
+        assert_eq!(config.get_str("out").unwrap(), "/mnt/nas");
+    }
+
+    #[test]
+    fn site_wins_over_profile_and_matches_subdomains() {
+        let config = config(CONFIG)
+            .resolve(Some("nas"), Some("https://n1.kemono.cr/patreon/user/456"))
+            .unwrap();
+        assert_eq!(config.table.get("task"), Some(&Value::Integer(3)));
+        assert_eq!(config.get_str("out").unwrap(), "/mnt/nas");
+    }
+
+    #[test]
+    fn top_level_keys_without_sections() {
+        let config = config(CONFIG)
+            .resolve(None, Some("https://coomer.st/onlyfans/user/123"))
+            .unwrap();
+        assert_eq!(config.table.get("task"), Some(&Value::Integer(1)));
+        assert_eq!(config.get_str("proxy").unwrap(), "top");
+    }
+
+    #[test]
+    fn unknown_profile_is_an_error() {
+        assert!(config(CONFIG).resolve(Some("missing"), None).is_err());
+    }
+
+    #[test]
+    fn command_line_wins_over_config() {
+        let config = config(CONFIG).resolve(Some("nas"), None).unwrap();
+        assert_eq!(
+            to_args(&config, &["--task", "8"]),
+            ["--out=/mnt/nas", "--proxy=top"]
+        );
+    }
+
+    #[test]
+    fn flags_and_lists() {
+        let config = config(
+            r#"
+            video-only = true
+            image-only = false
+            only = ["image", "audio"]
+            profile = "nas"
+            "#,
+        );
+        assert_eq!(
+            to_args(&config, &[]),
+            ["--only=image", "--only=audio", "--video-only"]
+        );
+    }
+
+    #[test]
+    fn command_line_selection_drops_the_config_one() {
+        let config = config(
+            r#"
+            task = 2
+            video-only = true
+            only = ["audio"]
+            "#,
+        );
+        assert_eq!(to_args(&config, &["--image-only"]), ["--task=2"]);
+        assert_eq!(to_args(&config, &["--only", "image"]), ["--task=2"]);
+    }
+
+    #[test]
+    fn unknown_key_is_an_error() {
+        let misspelled = Config {
+            table: "task = 2\nlimit_rate = \"1M\"".parse().unwrap(),
+            path: Some(PathBuf::from("/home/me/cktool.toml")),
+        };
+        let command = command();
+        let matches = command.clone().try_get_matches_from(["cktool"]).unwrap();
+        let err = misspelled
+            .to_args(&command, &matches)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`limit_rate`"), "{}", err);
+        assert!(err.contains("/home/me/cktool.toml"), "{}", err);
+        // sections are not options.
+        let config = config(
+            r#"
+            task = 2
+            [profiles.nas]
+            out = "/mnt/nas"
+            [categories]
+            comic = ["cbz"]
+            "#,
+        );
+        assert_eq!(to_args(&config, &[]), ["--task=2"]);
+    }
+
+    #[test]
+    fn subcommand_options_go_to_the_subcommand() {
+        let config = config("task = 2\ninterval = 600\njitter = 30");
+        let command = command();
+        let matches = command
+            .clone()
+            .try_get_matches_from(["cktool", "watch", "--jitter", "5"])
+            .unwrap();
+        assert_eq!(config.to_args(&command, &matches).unwrap(), ["--task=2"]);
+        assert_eq!(
+            config.subcommand_args(&command, &matches),
+            ["--interval=600"]
+        );
+
+        let matches = command.clone().try_get_matches_from(["cktool"]).unwrap();
+        assert!(config.subcommand_args(&command, &matches).is_empty());
+    }
+}
@@ -7,3 +7,127 @@ pub const TASK: usize = 8;
 pub const TOO_MANY_REQUESTS_DELAY_SEC: u64 = 2;
 // delay before re-download after found 'any' request error.
 pub const ERROR_REQUEST_DELAY_SEC: u64 = 2;
+
+// bytes of an invalid body kept in the log.
+pub const LOG_BODY_LIMIT: usize = 64 * 1024;
+
+// number of post ids buffered between page fetching and the download workers.
+pub const POST_QUEUE_SIZE: usize = 100;
+
+// number of posts in each page of the API.
+pub const PAGE_SIZE: u64 = 50;
+// default number of API pages fetched at the same time.
+pub const API_TASK: usize = 4;
+
+// number of parallel connections used for a single large file.
+pub const SEGMENTS: usize = 4;
+// files larger than this are downloaded in segments (100 MiB).
+pub const SEGMENT_THRESHOLD: u64 = 100 * 1024 * 1024;
+
+// default number of API requests per second shared by all tasks.
+pub const API_RPS: f64 = 10.0;
+// the API rate never drops below this after 'too many requests' errors.
+pub const API_MIN_RPS: f64 = 0.5;
+// minimum time between two decreases of the API rate.
+pub const API_RPS_DECREASE_INTERVAL_SEC: u64 = 2;
+
+// adaptive mode starts with this number of tasks.
+pub const ADAPTIVE_START_TASK: usize = 2;
+// throughput is measured over windows of this length.
+pub const ADAPTIVE_WINDOW_SEC: u64 = 5;
+// minimum time between two decreases of the task count.
+pub const ADAPTIVE_DECREASE_INTERVAL_SEC: u64 = 2;
+
+// job manifest kept in the output folder to resume interrupted runs.
+pub const MANIFEST_FILE: &str = ".cktool-manifest.json";
+// minimum time between two saves of the manifest while downloading.
+pub const MANIFEST_SAVE_INTERVAL_SEC: u64 = 5;
+
+// time to wait for a connection to be established.
+pub const CONNECT_TIMEOUT_SEC: u64 = 10;
+// time to wait for the response of a request, API bodies included.
+pub const REQUEST_TIMEOUT_SEC: u64 = 30;
+// a download is retried when no bytes arrived for this long.
+pub const STALL_TIMEOUT_SEC: u64 = 30;
+
+// number of creators of `cktool sync` downloaded at the same time.
+pub const SYNC_CREATORS: usize = 4;
+
+// seconds between two polls of a creator by `cktool watch`.
+pub const WATCH_INTERVAL_SEC: u64 = 900;
+// random seconds added to each poll.
+pub const WATCH_JITTER_SEC: u64 = 60;
+// longest seconds between two polls of a failing creator.
+pub const WATCH_MAX_BACKOFF_SEC: u64 = 4 * 3600;
+
+/// Exit code of the process, for scripts and cron jobs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exit {
+    /// every file was downloaded or skipped.
+    Success = 0,
+    /// some files or posts failed, the others were downloaded.
+    Partial = 1,
+    /// invalid url, option or config file, the same code as a usage error.
+    InvalidInput = 2,
+    /// the run stopped on an error or nothing could be downloaded.
+    Failure = 3,
+    /// stopped by Ctrl+C, like a shell does for SIGINT.
+    Interrupted = 130,
+}
+
+impl Exit {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// Exit code of several runs, e.g. one per favorite creator.
+    ///
+    /// The most severe code is kept, a run which failed next to one which
+    /// downloaded files gives `Partial`.
+    pub fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (Exit::Interrupted, _) | (_, Exit::Interrupted) => Exit::Interrupted,
+            (Exit::InvalidInput, _) | (_, Exit::InvalidInput) => Exit::InvalidInput,
+            (a, b) if a == b => a,
+            _ => Exit::Partial,
+        }
+    }
+}
+
+impl From<Exit> for std::process::ExitCode {
+    fn from(exit: Exit) -> Self {
+        std::process::ExitCode::from(exit as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_keeps_the_most_severe_code() {
+        assert_eq!(Exit::Success.merge(Exit::Success), Exit::Success);
+        assert_eq!(Exit::Failure.merge(Exit::Failure), Exit::Failure);
+        assert_eq!(Exit::Partial.merge(Exit::Success), Exit::Partial);
+        // a run which failed next to one which downloaded files.
+        assert_eq!(Exit::Success.merge(Exit::Failure), Exit::Partial);
+        assert_eq!(Exit::Failure.merge(Exit::Partial), Exit::Partial);
+    }
+
+    #[test]
+    fn merge_keeps_interrupts_and_input_errors() {
+        for other in [
+            Exit::Success,
+            Exit::Partial,
+            Exit::InvalidInput,
+            Exit::Failure,
+        ] {
+            assert_eq!(Exit::Interrupted.merge(other), Exit::Interrupted);
+            assert_eq!(other.merge(Exit::Interrupted), Exit::Interrupted);
+        }
+        for other in [Exit::Success, Exit::Partial, Exit::Failure] {
+            assert_eq!(Exit::InvalidInput.merge(other), Exit::InvalidInput);
+            assert_eq!(other.merge(Exit::InvalidInput), Exit::InvalidInput);
+        }
+    }
+}
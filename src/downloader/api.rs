@@ -0,0 +1,42 @@
+use reqwest::{Response, StatusCode};
+
+use crate::{logging::API, request};
+use tracing::{debug, warn};
+
+use super::Downloader;
+
+impl Downloader {
+    /// Send a GET request to an API endpoint.
+    ///
+    /// Every API request goes through the shared rate limiter, which slows
+    /// down on 'too many requests' and recovers on success.
+    pub async fn api_get(&self, url: &str) -> anyhow::Result<Response> {
+        self.api_limiter.acquire().await;
+        let res = self
+            .request
+            .send(request::new(&self.request)?.get(url))
+            .await;
+        self.count_request(url, res.as_ref().ok().map(|res| res.status()))
+            .await;
+        let res = res.inspect_err(|err| warn!(target: API, url, error = %err, "request failed"))?;
+        debug!(target: API, url, status = res.status().as_u16());
+        if res.status() == StatusCode::TOO_MANY_REQUESTS {
+            warn!(target: API, url, rate = self.api_limiter.current_rate(), "too many requests");
+            self.api_limiter.too_many_requests();
+            self.concurrency.report_error();
+        } else if res.status().is_server_error() {
+            self.concurrency.report_error();
+        } else if res.status().is_success() {
+            self.api_limiter.success();
+        }
+        Ok(res)
+    }
+
+    /// Count a request in the per host statistics, `status` is none when no response was received.
+    pub(super) async fn count_request(&self, url: &str, status: Option<StatusCode>) {
+        self.info
+            .lock()
+            .await
+            .add_request(url, status.map(|status| status.as_u16()));
+    }
+}
@@ -0,0 +1,254 @@
+use chrono::NaiveDate;
+use futures_util::lock::Mutex;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+use crate::{
+    category::FileFilter,
+    declare::{API_RPS, API_TASK, RetryType, SEGMENT_THRESHOLD, SEGMENTS, TASK, TaskType},
+    link::Link,
+    request::RequestOptions,
+    utils::{ApiLimiter, Bandwidth, Shutdown},
+};
+
+use super::{
+    Downloader,
+    concurrency::Concurrency,
+    event::{Event, Events},
+    info::DownloaderInfo,
+    manifest::Manifest,
+    page_status::PostCounter,
+    space::DiskSpace,
+};
+
+/// Settings of a `Downloader`, each one has a default.
+///
+/// Started with `Downloader::builder`, nothing is printed by the downloader:
+/// progress is only reported to the handlers given to `on_event`.
+pub struct DownloaderBuilder {
+    link: Link,
+    task: TaskType,
+    outdir: String,
+    retry: Option<RetryType>,
+    video_only: bool,
+    image_only: bool,
+    verbose: bool,
+    segments: usize,
+    segment_threshold: u64,
+    max_connections: Option<usize>,
+    bandwidth: Bandwidth,
+    api_rps: f64,
+    api_task: usize,
+    adaptive: bool,
+    refresh: bool,
+    force: bool,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    filter: FileFilter,
+    request: RequestOptions,
+    shutdown: Arc<Shutdown>,
+    since: Option<NaiveDate>,
+    until_known: bool,
+    events: Events,
+}
+
+impl DownloaderBuilder {
+    pub fn new(link: Link) -> Self {
+        Self {
+            link,
+            task: TASK,
+            outdir: ".".to_string(),
+            retry: None,
+            video_only: false,
+            image_only: false,
+            verbose: false,
+            segments: SEGMENTS,
+            segment_threshold: SEGMENT_THRESHOLD,
+            max_connections: None,
+            bandwidth: Bandwidth::unlimited(),
+            api_rps: API_RPS,
+            api_task: API_TASK,
+            adaptive: false,
+            refresh: false,
+            force: false,
+            min_size: None,
+            max_size: None,
+            filter: FileFilter::default(),
+            request: RequestOptions::default(),
+            shutdown: Arc::new(Shutdown::new()),
+            since: None,
+            until_known: false,
+            events: Events::default(),
+        }
+    }
+
+    /// Number of posts downloaded at the same time.
+    pub fn task(mut self, task: TaskType) -> Self {
+        self.task = task;
+        self
+    }
+
+    pub fn outdir(mut self, outdir: impl Into<String>) -> Self {
+        self.outdir = outdir.into();
+        self
+    }
+
+    /// Attempts of each request, ten times the number of tasks by default.
+    pub fn retry(mut self, retry: RetryType) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    pub fn video_only(mut self, video_only: bool) -> Self {
+        self.video_only = video_only;
+        self
+    }
+
+    pub fn image_only(mut self, image_only: bool) -> Self {
+        self.image_only = image_only;
+        self
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Files larger than `threshold` are split into `segments` byte ranges.
+    pub fn segments(mut self, segments: usize, threshold: u64) -> Self {
+        self.segments = segments;
+        self.segment_threshold = threshold;
+        self
+    }
+
+    /// Download connections opened at the same time, tasks and segments included.
+    pub fn max_connections(mut self, connections: usize) -> Self {
+        self.max_connections = Some(connections);
+        self
+    }
+
+    pub fn bandwidth(mut self, bandwidth: Bandwidth) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+
+    /// API requests per second, lowered on 429 answers.
+    pub fn api_rps(mut self, api_rps: f64) -> Self {
+        self.api_rps = api_rps;
+        self
+    }
+
+    /// Number of API pages fetched at the same time.
+    pub fn api_task(mut self, api_task: usize) -> Self {
+        self.api_task = api_task;
+        self
+    }
+
+    /// Scale the number of tasks on server feedback, `task` is the maximum.
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Fetch pages and unfinished posts again instead of using the manifest.
+    pub fn refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Download even when the files would not fit on the disk.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Files outside of these sizes are skipped.
+    pub fn size_range(mut self, min_size: Option<u64>, max_size: Option<u64>) -> Self {
+        self.min_size = min_size;
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn filter(mut self, filter: FileFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Proxy, headers and cookies, the site of the link is added to the API hosts.
+    pub fn request(mut self, request: RequestOptions) -> Self {
+        self.request = request;
+        self
+    }
+
+    /// Stop the run when shutdown is requested, e.g. by Ctrl+C.
+    pub fn shutdown(mut self, shutdown: Arc<Shutdown>) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Only download posts published on or after this day.
+    pub fn since(mut self, since: Option<NaiveDate>) -> Self {
+        self.since = since;
+        self
+    }
+
+    /// Stop fetching pages at the first post already in the manifest.
+    pub fn until_known(mut self, until_known: bool) -> Self {
+        self.until_known = until_known;
+        self
+    }
+
+    /// Call `handler` for every event of the run.
+    pub fn on_event(mut self, handler: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        self.events.subscribe(handler);
+        self
+    }
+
+    /// Receive every event of the run through a channel.
+    pub fn event_channel(&mut self) -> tokio::sync::mpsc::UnboundedReceiver<Event> {
+        self.events.channel()
+    }
+
+    pub fn build(self) -> Downloader {
+        let task = self.task.max(1);
+        let mut downloader = Downloader {
+            link: self.link,
+            task_limit: task,
+            outdir: self.outdir,
+            retry: self.retry.unwrap_or(task as RetryType * 10),
+            video_only: self.video_only,
+            image_only: self.image_only,
+            events: self.events,
+            info: Arc::new(Mutex::new(DownloaderInfo::new())),
+            verbose: self.verbose,
+            creator_name: Arc::new(Mutex::new(None)),
+            post_counter: Arc::new(PostCounter::default()),
+            segments: self.segments.max(1),
+            segment_threshold: self.segment_threshold,
+            connections: Arc::new(Semaphore::new(task + self.segments.max(1))),
+            bandwidth: Arc::new(self.bandwidth),
+            api_limiter: Arc::new(ApiLimiter::new(self.api_rps)),
+            api_task: self.api_task.max(1),
+            concurrency: Arc::new(Concurrency::new(task, self.adaptive)),
+            shutdown: self.shutdown,
+            stop: Arc::new(Shutdown::new()),
+            manifest: Arc::new(Mutex::new(Manifest::default())),
+            refresh: self.refresh,
+            request: self.request,
+            force: self.force,
+            space: Arc::new(DiskSpace::default()),
+            min_size: self.min_size,
+            max_size: self.max_size,
+            filter: Arc::new(FileFilter::default()),
+            since: self.since,
+            until_known: self.until_known,
+        };
+        if let Some(connections) = self.max_connections {
+            downloader.set_max_connections(connections);
+        }
+        downloader.set_filter(self.filter);
+        let domain = downloader.link.domain.clone();
+        downloader.request.add_api_host(&domain);
+        downloader
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+
+use crate::declare::{ADAPTIVE_DECREASE_INTERVAL_SEC, ADAPTIVE_START_TASK, ADAPTIVE_WINDOW_SEC};
+
+use super::event::{Event, Events};
+
+struct Window {
+    started: Instant,
+    errors: u64,
+    last_throughput: f64,
+    last_decrease: Option<Instant>,
+}
+
+/// Number of workers allowed to download at the same time.
+///
+/// In adaptive mode the level starts low, grows by one while throughput
+/// improves without errors and is halved on 429/5xx responses. Otherwise it
+/// stays at `max` for the whole run.
+pub struct Concurrency {
+    adaptive: bool,
+    max: usize,
+    level: AtomicUsize,
+    active: AtomicUsize,
+    bytes: AtomicU64,
+    window: Mutex<Window>,
+    notify: Notify,
+    /// handlers of the runs sharing this instance, with the id given by `set_events`.
+    events: Mutex<Vec<(u64, Events)>>,
+    next_id: AtomicU64,
+}
+
+/// Keeps a worker slot until dropped.
+pub struct ConcurrencyPermit<'a> {
+    concurrency: &'a Concurrency,
+}
+
+impl Drop for ConcurrencyPermit<'_> {
+    fn drop(&mut self) {
+        self.concurrency.active.fetch_sub(1, Ordering::SeqCst);
+        self.concurrency.notify.notify_waiters();
+    }
+}
+
+impl Concurrency {
+    pub fn new(max: usize, adaptive: bool) -> Self {
+        let max = max.max(1);
+        let level = if adaptive {
+            ADAPTIVE_START_TASK.min(max)
+        } else {
+            max
+        };
+        Self {
+            adaptive,
+            max,
+            level: AtomicUsize::new(level),
+            active: AtomicUsize::new(0),
+            bytes: AtomicU64::new(0),
+            window: Mutex::new(Window {
+                started: Instant::now(),
+                errors: 0,
+                last_throughput: 0.0,
+                last_decrease: None,
+            }),
+            notify: Notify::new(),
+            events: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_adaptive(&self) -> bool {
+        self.adaptive
+    }
+
+    pub fn level(&self) -> usize {
+        self.level.load(Ordering::SeqCst)
+    }
+
+    /// Report each change of the level as `Event::Concurrency`, returns the id
+    /// to pass to `finish`.
+    ///
+    /// Runs sharing the workers share the level too, it is only reported to
+    /// the oldest run still going.
+    pub fn set_events(&self, events: Events) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.events.lock().unwrap().push((id, events));
+        self.show(0.0);
+        id
+    }
+
+    /// Stop reporting the level to the run of `id`.
+    pub fn finish(&self, id: u64) {
+        self.events.lock().unwrap().retain(|(run, _)| *run != id);
+    }
+
+    /// Wait for a free worker slot.
+    pub async fn acquire(&self) -> ConcurrencyPermit<'_> {
+        loop {
+            let notified = self.notify.notified();
+            let active = self.active.load(Ordering::SeqCst);
+            if active < self.level()
+                && self
+                    .active
+                    .compare_exchange(active, active + 1, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            {
+                return ConcurrencyPermit { concurrency: self };
+            }
+            // re-check from time to time, the level may grow while waiting.
+            let _ = tokio::time::timeout(Duration::from_secs(1), notified).await;
+        }
+    }
+
+    /// Count downloaded bytes for the throughput of the current window.
+    pub fn report_bytes(&self, bytes: u64) {
+        if !self.adaptive {
+            return;
+        }
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.evaluate();
+    }
+
+    /// The server answered 429 or 5xx, shrink quickly.
+    pub fn report_error(&self) {
+        if !self.adaptive {
+            return;
+        }
+        let mut window = self.window.lock().unwrap();
+        window.errors += 1;
+        let now = Instant::now();
+        if let Some(last) = window.last_decrease
+            && now.duration_since(last) < Duration::from_secs(ADAPTIVE_DECREASE_INTERVAL_SEC)
+        {
+            return;
+        }
+        window.last_decrease = Some(now);
+        let level = (self.level() / 2).max(1);
+        self.level.store(level, Ordering::SeqCst);
+        let throughput = window.last_throughput;
+        drop(window);
+        self.show(throughput);
+    }
+
+    /// Grow by one at the end of a window when throughput improved without errors.
+    fn evaluate(&self) {
+        let mut window = self.window.lock().unwrap();
+        let elapsed = window.started.elapsed();
+        if elapsed < Duration::from_secs(ADAPTIVE_WINDOW_SEC) {
+            return;
+        }
+        let throughput = self.bytes.swap(0, Ordering::Relaxed) as f64 / elapsed.as_secs_f64();
+        let level = self.level();
+        // only grow when the current level is really used.
+        if window.errors == 0
+            && throughput > window.last_throughput * 1.05
+            && self.active.load(Ordering::SeqCst) >= level
+            && level < self.max
+        {
+            self.level.store(level + 1, Ordering::SeqCst);
+            self.notify.notify_waiters();
+        }
+        window.last_throughput = throughput;
+        window.errors = 0;
+        window.started = Instant::now();
+        drop(window);
+        self.show(throughput);
+    }
+
+    fn show(&self, throughput: f64) {
+        if let Some((_, events)) = self.events.lock().unwrap().first() {
+            events.emit(Event::Concurrency {
+                level: self.level(),
+                max: self.max,
+                throughput: throughput as u64,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// End the current window, the next bytes evaluate it.
+    fn end_window(concurrency: &Concurrency) {
+        concurrency.window.lock().unwrap().started =
+            Instant::now() - Duration::from_secs(ADAPTIVE_WINDOW_SEC);
+    }
+
+    /// Let the next error lower the level again.
+    fn after_decrease_interval(concurrency: &Concurrency) {
+        let mut window = concurrency.window.lock().unwrap();
+        window.last_decrease = window
+            .last_decrease
+            .map(|last| last - Duration::from_secs(ADAPTIVE_DECREASE_INTERVAL_SEC));
+    }
+
+    async fn blocks(concurrency: &Concurrency) -> bool {
+        tokio::time::timeout(Duration::from_millis(50), concurrency.acquire())
+            .await
+            .is_err()
+    }
+
+    #[tokio::test]
+    async fn fixed_level_without_adaptive() {
+        let concurrency = Concurrency::new(3, false);
+        assert_eq!(concurrency.level(), 3);
+        concurrency.report_error();
+        end_window(&concurrency);
+        concurrency.report_bytes(1 << 20);
+        assert_eq!(concurrency.level(), 3);
+
+        let permits = [
+            concurrency.acquire().await,
+            concurrency.acquire().await,
+            concurrency.acquire().await,
+        ];
+        assert!(blocks(&concurrency).await);
+        drop(permits);
+        assert!(!blocks(&concurrency).await);
+    }
+
+    #[tokio::test]
+    async fn grows_by_one_up_to_the_number_of_tasks() {
+        let concurrency = Concurrency::new(3, true);
+        assert_eq!(concurrency.level(), 2);
+        let mut permits = vec![concurrency.acquire().await, concurrency.acquire().await];
+        end_window(&concurrency);
+        concurrency.report_bytes(1 << 20);
+        assert_eq!(concurrency.level(), 3);
+
+        permits.push(concurrency.acquire().await);
+        end_window(&concurrency);
+        concurrency.report_bytes(1 << 30);
+        assert_eq!(concurrency.level(), 3);
+    }
+
+    #[tokio::test]
+    async fn no_growth_after_errors_or_when_idle() {
+        let concurrency = Concurrency::new(8, true);
+        let level = concurrency.level();
+        // the current level is not used.
+        end_window(&concurrency);
+        concurrency.report_bytes(1 << 20);
+        assert_eq!(concurrency.level(), level);
+
+        let mut permits = Vec::new();
+        for _ in 0..level {
+            permits.push(concurrency.acquire().await);
+        }
+        concurrency.window.lock().unwrap().errors = 1;
+        end_window(&concurrency);
+        concurrency.report_bytes(1 << 30);
+        assert_eq!(concurrency.level(), level);
+    }
+
+    #[test]
+    fn errors_halve_the_level_down_to_one() {
+        let concurrency = Concurrency::new(16, true);
+        concurrency.level.store(16, Ordering::SeqCst);
+        concurrency.report_error();
+        assert_eq!(concurrency.level(), 8);
+        // the same burst reported by other workers.
+        concurrency.report_error();
+        assert_eq!(concurrency.level(), 8);
+        for _ in 0..5 {
+            after_decrease_interval(&concurrency);
+            concurrency.report_error();
+        }
+        assert_eq!(concurrency.level(), 1);
+    }
+}
@@ -1,141 +1,529 @@
 use anyhow::Context;
-use chrono::Local;
-use futures_util::StreamExt;
-use indicatif::{ProgressBar, ProgressStyle};
-use reqwest::{StatusCode, header::RANGE};
+use reqwest::{
+    Response, StatusCode,
+    header::{CONTENT_TYPE, RANGE},
+};
+use size::Size;
 use std::cmp::min;
 use tokio::{
-    fs::OpenOptions,
     io::{AsyncWriteExt, BufWriter},
     time::{Duration, sleep},
 };
+use tracing::{debug, info, warn};
 
 use crate::{
-    declare::{ERROR_REQUEST_DELAY_SEC, TOO_MANY_REQUESTS_DELAY_SEC},
-    downloader::print::ProgressDisplay,
+    category::FilterResult,
+    declare::{ERROR_REQUEST_DELAY_SEC, LOG_BODY_LIMIT, TOO_MANY_REQUESTS_DELAY_SEC},
+    logging::{DOWNLOAD, RETRY},
     request,
+    utils::{SNIFF_BYTES, check_magic, read_head},
 };
 use std::path::Path;
 
-const IMAGE_EXTENSIONS: &[&str] = &[
-    "jpg", "jpeg", "png", "gif", "webp", "bmp", "tiff", "svg", "heic",
-];
-const VIDEO_EXTENSIONS: &[&str] = &[
-    "mp4", "webm", "mkv", "avi", "mov", "flv", "wmv", "mpg", "mpeg", "m4v",
-];
+use super::{
+    Downloader,
+    estimate::header_size,
+    event::{Event, Wait},
+    info::{DownloaderInfo, FileResult},
+    manifest::FileStatus,
+    page_status::StatusBar,
+};
 
-use super::{Downloader, info::DownloaderInfo, page_status::StatusBar};
+/// A file downloaded by segments, see `Downloader::finish_segments`.
+struct SegmentedFile<'a> {
+    url: &'a str,
+    path: &'a str,
+    fname: &'a str,
+    path_to_file: &'a str,
+    total_size: u64,
+}
 
 impl Downloader {
-    async fn log_status(
+    /// Reason to skip a file by its name, files without extension are checked by `type_filter`.
+    pub fn name_filter(&self, fname: &str) -> Option<String> {
+        match self.filter.check_name(fname) {
+            FilterResult::Skip(reason) => Some(reason),
+            FilterResult::Keep | FilterResult::Unknown => None,
+        }
+    }
+
+    /// Reason to skip a file without extension by the `Content-Type` of the response.
+    fn type_filter(&self, fname: &str, res: &Response) -> Option<String> {
+        if !matches!(self.filter.check_name(fname), FilterResult::Unknown) {
+            return None;
+        }
+        self.filter.check_content_type(
+            res.headers()
+                .get(CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok()),
+        )
+    }
+
+    /// Reason to skip a file of this size by `min_size` or `max_size`.
+    pub fn size_filter(&self, size: u64) -> Option<String> {
+        if let Some(min_size) = self.min_size
+            && size < min_size
+        {
+            return Some(format!("smaller than {}", Size::from_bytes(min_size)));
+        }
+        if let Some(max_size) = self.max_size
+            && size > max_size
+        {
+            return Some(format!("larger than {}", Size::from_bytes(max_size)));
+        }
+        None
+    }
+
+    async fn log_status(&self, post_url: &str, file_name: &str, status: &str) {
+        let creator = self.creator_name.lock().await.clone().unwrap_or_default();
+        if status == "failed" {
+            warn!(target: DOWNLOAD, creator, post = post_url, file = file_name, status);
+        } else {
+            info!(target: DOWNLOAD, creator, post = post_url, file = file_name, status);
+        }
+    }
+
+    pub(super) fn emit_finished(
+        &self,
+        status: &StatusBar,
+        url: &str,
+        fname: &str,
+        size: Option<u64>,
+    ) {
+        self.events.emit(Event::FileFinished {
+            post: status.post.clone(),
+            url: url.to_string(),
+            name: fname.to_string(),
+            size,
+        });
+    }
+
+    pub(super) fn emit_failed(
+        &self,
+        status: &StatusBar,
+        url: &str,
+        fname: &str,
+        reason: impl Into<String>,
+    ) {
+        self.events.emit(Event::FileFailed {
+            post: status.post.clone(),
+            url: url.to_string(),
+            name: fname.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    pub(super) fn emit_interrupted(&self, status: &StatusBar, url: &str, fname: &str) {
+        self.events.emit(Event::FileInterrupted {
+            post: status.post.clone(),
+            url: url.to_string(),
+            name: fname.to_string(),
+        });
+    }
+
+    fn emit_skipped(&self, status: &StatusBar, url: &str, fname: &str, reason: &str) {
+        self.events.emit(Event::FileSkipped {
+            post: status.post.clone(),
+            url: url.to_string(),
+            name: fname.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Log a body which does not match its file type, e.g. an HTML error page.
+    ///
+    /// The body itself is only logged at the debug level.
+    pub(super) fn log_body(&self, post_url: &str, file_name: &str, reason: &str, body: &[u8]) {
+        warn!(target: DOWNLOAD, post = post_url, file = file_name, reason, "invalid content");
+        let body = &body[..min(body.len(), LOG_BODY_LIMIT)];
+        debug!(
+            target: DOWNLOAD,
+            file = file_name,
+            body = %String::from_utf8_lossy(body),
+            "invalid content body"
+        );
+    }
+
+    /// Remove a downloaded file whose content does not match its type.
+    ///
+    /// The body is kept in the log and the file counts as failed.
+    pub(super) async fn reject_content(
         &self,
-        post_url: &str,
-        file_name: &str,
-        status: &str,
+        url: &str,
+        fname: &str,
+        path_to_file: &str,
+        reason: &str,
     ) -> anyhow::Result<()> {
-        if self.verbose {
-            let date = Local::now().format("%Y-%m-%d");
-            let creator = self.creator_name.lock().await.clone().unwrap_or_default();
-            let log_file_name = format!("{}_{}.log", date, creator);
-            let mut file = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(log_file_name)
-                .await?;
-            let log_entry = format!(
-                "Post URL: {}, File: {}, Status: {}\n",
-                post_url, file_name, status
-            );
-            file.write_all(log_entry.as_bytes()).await?;
-        }
+        let body = tokio::fs::read(path_to_file).await.unwrap_or_default();
+        self.log_body(url, fname, reason, &body);
+        let _ = tokio::fs::remove_file(path_to_file).await;
         Ok(())
     }
+
     /// Downloads all files in a specific post
     ///
     /// # Arguments
     /// * `pid` - post id to download
-    /// * `page` - Current page information for progress display
+    /// * `status` - post taken by the worker, given to the events of its files
     pub async fn download_post(
         &mut self,
         pid: String,
         status: StatusBar,
     ) -> anyhow::Result<DownloaderInfo> {
         let url = self.link.post_id(&pid);
-        let posts = match self.get_posts_from_page(&url).await {
-            Ok(v) => v,
-            Err(_) => {
-                {
-                    self.info
-                        .lock()
-                        .await
-                        .add_failed_file(url.replace("api/v1/", ""));
+
+        // files of a post resolved by an earlier run are taken from the manifest.
+        let cached = if self.refresh {
+            None
+        } else {
+            self.manifest
+                .lock()
+                .await
+                .post(&pid)
+                .filter(|post| post.resolved)
+                .map(|post| post.files.iter().map(|f| f.url.clone()).collect::<Vec<_>>())
+        };
+        let posts = match cached {
+            Some(v) => v,
+            None => match self.get_posts_from_page(&url).await {
+                Ok(v) => {
+                    self.manifest.lock().await.set_files(&pid, &v);
+                    self.events.emit(Event::PostResolved {
+                        post: pid.clone(),
+                        files: v.len(),
+                    });
+                    v
                 }
+                Err(_) => {
+                    {
+                        self.info
+                            .lock()
+                            .await
+                            .add_failed_post(url.replace("api/v1/", ""));
+                    }
+                    self.events.emit(Event::PostFailed {
+                        post: pid.clone(),
+                        url: url.replace("api/v1/", ""),
+                    });
 
-                return Ok(DownloaderInfo::new());
-            }
+                    return Ok(DownloaderInfo::new());
+                }
+            },
         };
 
         let mut download_info = DownloaderInfo::new();
 
         for path in posts {
             let outdir = self.outdir.clone();
-            let mc = self.multi_progress.clone();
             let fname = if let Ok(v) = path.split("/").last().context("Invalid file path") {
                 v
             } else {
-                eprintln!("Invalid file path");
+                warn!(target: DOWNLOAD, post = url, path, "invalid file path");
                 continue;
             };
+            let path_to_file = format!("{}/{}", outdir, fname);
+
+            // finished by an earlier run, it still counts as a success of this one.
+            let (known, known_size) = self
+                .manifest
+                .lock()
+                .await
+                .file(&pid, &path)
+                .map_or((None, None), |file| (Some(file.status), file.size));
+            if known == Some(FileStatus::Done) && Path::new(&path_to_file).exists() {
+                download_info.add_success_file(1);
+                download_info.add_file_result(FileResult {
+                    post: pid.clone(),
+                    url: path.clone(),
+                    status: FileStatus::Done,
+                    http_code: None,
+                    size: known_size,
+                });
+                continue;
+            }
 
             // Filtering logic
-            let file_extension = Path::new(&fname)
-                .extension()
-                .and_then(|s| s.to_str())
-                .map(|s| s.to_lowercase());
-
-            let skip_file = if let Some(ext) = &file_extension
-                && (self.video_only && !VIDEO_EXTENSIONS.contains(&ext.as_str())
-                    || self.image_only && !IMAGE_EXTENSIONS.contains(&ext.as_str()))
-                && (self.video_only || self.image_only)
+            if let Some(reason) = self.name_filter(fname) {
+                self.emit_skipped(&status, &path, fname, &reason);
+                download_info.add_skip_file(path.clone(), reason);
+                download_info.add_file_result(FileResult {
+                    post: pid.clone(),
+                    url: path.clone(),
+                    status: FileStatus::Skipped,
+                    http_code: None,
+                    size: None,
+                });
+                self.log_status(&url, fname, "skipped").await;
+                self.manifest
+                    .lock()
+                    .await
+                    .set_status(&pid, &path, FileStatus::Skipped, None);
+                continue;
+            }
+
+            // the files left after Ctrl+C are downloaded again by the next run.
+            if self.is_stopping() {
+                download_info.add_interrupted_file(path.clone());
+                download_info.add_file_result(FileResult {
+                    post: pid.clone(),
+                    url: path.clone(),
+                    status: match known {
+                        Some(FileStatus::Partial) => FileStatus::Partial,
+                        _ => FileStatus::Pending,
+                    },
+                    http_code: None,
+                    size: None,
+                });
+                self.log_status(&url, fname, "interrupted").await;
+                self.emit_interrupted(&status, &path, fname);
+                continue;
+            }
+
+            let (file_status, size) = self
+                .download_file(
+                    &url,
+                    &path,
+                    fname,
+                    &path_to_file,
+                    &status,
+                    &mut download_info,
+                )
+                .await?;
+            let http_code = download_info.take_http_code();
+            download_info.add_file_result(FileResult {
+                post: pid.clone(),
+                url: path.clone(),
+                status: file_status,
+                http_code,
+                size,
+            });
+            self.manifest
+                .lock()
+                .await
+                .set_status(&pid, &path, file_status, size);
+        }
+
+        Ok(download_info)
+    }
+
+    /// Downloads a single file of a post, resumes from the size on disk.
+    ///
+    /// Returns the final status of the file and its total size when known.
+    async fn download_file(
+        &self,
+        url: &str,
+        path: &str,
+        fname: &str,
+        path_to_file: &str,
+        status: &StatusBar,
+        download_info: &mut DownloaderInfo,
+    ) -> anyhow::Result<(FileStatus, Option<u64>)> {
+        let path = path.to_string();
+
+        // resume a large file which was split into byte ranges.
+        if let Some(res) = self.segment_head(&path, path_to_file).await
+            && let Some(total_size) = header_size(&res)
+        {
+            // the filters of this run apply to it too, the parts are kept for a later run.
+            if let Some(reason) = self
+                .size_filter(total_size)
+                .or_else(|| self.type_filter(fname, &res))
             {
-                true
+                self.emit_skipped(status, &path, fname, &reason);
+                download_info.add_skip_file(path.clone(), reason);
+                self.log_status(url, fname, "skipped").await;
+                return Ok((FileStatus::Skipped, Some(total_size)));
+            }
+            return self
+                .finish_segments(
+                    SegmentedFile {
+                        url,
+                        path: &path,
+                        fname,
+                        path_to_file,
+                        total_size,
+                    },
+                    status,
+                    download_info,
+                )
+                .await;
+        }
+
+        let mut retry = self.retry;
+        let mut retry_request = self.retry;
+        let mut download_counter = 0;
+        'request: loop {
+            if self.is_stopping() {
+                download_info.add_interrupted_file(path.clone());
+                self.emit_interrupted(status, &path, fname);
+                return Ok((FileStatus::Partial, None));
+            }
+            download_counter += 1;
+            let permit = self.connections.acquire().await?;
+            let (sender, file_size) = if let Ok(result) = Path::new(&path_to_file).try_exists()
+                && result
+            {
+                let file_size = tokio::fs::metadata(&path_to_file)
+                    .await
+                    .context("cannot get file size")?
+                    .len();
+                let create_sender = request::new(&self.request)?
+                    .get(&path)
+                    .header(RANGE, format!("bytes={}-", file_size));
+                (create_sender, Some(file_size))
             } else {
-                false
+                (request::new(&self.request)?.get(&path), None)
             };
 
-            if skip_file {
-                download_info.add_skip_file(path.clone()); // Assuming add_skipped_file exists or similar
-                self.log_status(&url, fname, "skipped").await?;
-                continue;
+            let res = self.request.send_file(sender).await;
+            self.count_request(&path, res.as_ref().ok().map(|res| res.status()))
+                .await;
+            if let Err(err) = &res {
+                warn!(target: RETRY, file = fname, error = %err, "request failed");
             }
+            if let Ok(res) = res {
+                download_info.set_http_code(res.status().as_u16());
+                let total_size = match res.content_length().context("Cannot get total size") {
+                    Ok(v) => v,
+                    Err(_) => {
+                        warn!(target: RETRY, file = fname, status = res.status().as_u16(), "no Content-Length");
+                        debug!(target: RETRY, file = fname, headers = ?res.headers(), "no Content-Length");
+                        if retry_request > 0 {
+                            retry_request -= 1;
+                            continue;
+                        }
+                        download_info.add_failed_file(path.clone());
+                        self.log_status(url, fname, "failed").await;
+                        self.emit_failed(status, &path, fname, "no Content-Length");
+                        return Ok((FileStatus::Failed, None));
+                    }
+                };
+                // a server ignoring the range sends the whole file, it is written again.
+                let file_size = file_size.filter(|_| res.status() != StatusCode::OK);
+                let mut downloaded: u64 = file_size.unwrap_or(0);
 
-            let path_to_file = format!("{}/{}", outdir, fname);
-
-            let mut retry = self.retry;
-            let mut retry_request = self.retry;
-            let mut download_counter = 0;
-            'request: loop {
-                download_counter += 1;
-                let url = url.clone();
-                let (sender, file_size) = if let Ok(result) = Path::new(&path_to_file).try_exists()
-                    && result
+                // size and type filters are checked before the body is streamed.
+                if (res.status() == StatusCode::OK || res.status() == StatusCode::PARTIAL_CONTENT)
+                    && let Some(reason) = self
+                        .size_filter(file_size.unwrap_or(0) + total_size)
+                        .or_else(|| self.type_filter(fname, &res))
                 {
-                    let file_size = tokio::fs::metadata(&path_to_file)
-                        .await
-                        .context("cannot get file size")?
-                        .len();
-                    let create_sender = request::new()?
-                        .get(&path)
-                        .header(RANGE, format!("bytes={}-", file_size));
-                    (create_sender, Some(file_size))
-                } else {
-                    (request::new()?.get(&path), None)
+                    self.emit_skipped(status, &path, fname, &reason);
+                    download_info.add_skip_file(path.clone(), reason);
+                    self.log_status(url, fname, "skipped").await;
+                    return Ok((
+                        FileStatus::Skipped,
+                        Some(file_size.unwrap_or(0) + total_size),
+                    ));
+                }
+
+                // large files are split into byte ranges over parallel connections.
+                if file_size.is_none() && self.accepts_segments(&res, total_size) {
+                    drop(res);
+                    drop(permit);
+                    return self
+                        .finish_segments(
+                            SegmentedFile {
+                                url,
+                                path: &path,
+                                fname,
+                                path_to_file,
+                                total_size,
+                            },
+                            status,
+                            download_info,
+                        )
+                        .await;
+                }
+
+                // this download was clompleted.
+                if 190 == res.status() || 416 == res.status() {
+                    self.events.emit(Event::FileStarted {
+                        url: path.clone(),
+                        name: fname.to_string(),
+                        size: downloaded,
+                        downloaded,
+                        attempt: download_counter,
+                        segments: 1,
+                        status: status.clone(),
+                    });
+                    download_info.add_success_file(1);
+                    self.emit_finished(status, &path, fname, file_size);
+                    return Ok((FileStatus::Done, file_size));
+                }
+
+                // prevent too many requests
+                if StatusCode::TOO_MANY_REQUESTS == res.status() {
+                    self.concurrency.report_error();
+                    if retry == 0 {
+                        self.emit_failed(status, &path, fname, "HTTP 429 Too Many Requests");
+                        download_info.add_failed_file(path.clone());
+                        self.log_status(url, fname, "failed").await;
+                        return Ok((FileStatus::Failed, None));
+                    }
+                    info!(target: RETRY, file = fname, delay_sec = TOO_MANY_REQUESTS_DELAY_SEC, "too many requests");
+                    retry -= 1;
+                    self.events.emit(Event::FileWaiting {
+                        url: path.clone(),
+                        name: fname.to_string(),
+                        wait: Wait::TooManyRequests {
+                            secs: TOO_MANY_REQUESTS_DELAY_SEC,
+                        },
+                        status: status.clone(),
+                    });
+                    // the connection is left to others while waiting.
+                    drop(permit);
+                    tokio::time::sleep(Duration::from_secs(TOO_MANY_REQUESTS_DELAY_SEC)).await;
+                    continue;
+                }
+                // prevent bad gateway: wait 2 secs and re-download
+                if StatusCode::OK != res.status() && StatusCode::PARTIAL_CONTENT != res.status() {
+                    if res.status().is_server_error() {
+                        self.concurrency.report_error();
+                    }
+                    if retry == 0 {
+                        self.emit_failed(status, &path, fname, format!("HTTP {}", res.status()));
+                        download_info.add_failed_file(path.clone());
+                        self.log_status(url, fname, "failed").await;
+                        return Ok((FileStatus::Failed, None));
+                    }
+                    info!(target: RETRY, file = fname, status = res.status().as_u16(), attempt = download_counter, "retry");
+                    self.events.emit(Event::FileWaiting {
+                        url: path.clone(),
+                        name: fname.to_string(),
+                        wait: Wait::Retry {
+                            status: res.status().as_u16(),
+                            attempt: download_counter,
+                            secs: ERROR_REQUEST_DELAY_SEC,
+                        },
+                        status: status.clone(),
+                    });
+                    retry -= 1;
+                    drop(permit);
+                    tokio::time::sleep(Duration::from_secs(ERROR_REQUEST_DELAY_SEC)).await;
+                    continue;
+                }
+
+                self.events.emit(Event::FileStarted {
+                    url: path.clone(),
+                    name: fname.to_string(),
+                    size: total_size + downloaded,
+                    downloaded,
+                    attempt: download_counter,
+                    segments: 1,
+                    status: status.clone(),
+                });
+
+                // stop the run before the disk runs out.
+                let Some(mut reservation) = self.ensure_space(total_size).await else {
+                    download_info.add_interrupted_file(path.clone());
+                    self.emit_interrupted(status, &path, fname);
+                    return Ok((FileStatus::Partial, None));
                 };
 
-                // create or open file follow by file_size.
+                // the final file only exists once its bytes arrive.
                 let file = if file_size.is_some() {
                     tokio::fs::OpenOptions::new()
-                        .write(true)
                         .append(true)
                         .open(&path_to_file)
                         .await?
@@ -144,122 +532,173 @@ impl Downloader {
                 };
                 let mut file = BufWriter::new(file);
 
-                if let Ok(res) = sender.send().await {
-                    let mut downloaded: u64 = file_size.unwrap_or(0);
-                    let total_size = match res.content_length().context("Cannot get total size") {
-                        Ok(v) => v,
-                        Err(_) => {
-                            println!("{:?}", res.headers());
-                            eprintln!("Failed receive file size status: {}", res.status());
+                debug!(target: DOWNLOAD, file = fname, size = total_size, resume = downloaded, "start");
+                let mut stream = res.bytes_stream();
+                // the first bytes of every body are checked against the file type,
+                // with the bytes already on disk for a resumed one.
+                let mut head = Some(match file_size {
+                    Some(_) => read_head(path_to_file).await?,
+                    None => Vec::new(),
+                });
+                let mut invalid = None;
+                if let Some(buf) = head.take_if(|buf| buf.len() == SNIFF_BYTES) {
+                    invalid = check_magic(fname, &buf).err();
+                }
+
+                while invalid.is_none() {
+                    let item = match self.request.next_chunk(&mut stream).await {
+                        Ok(Some(Ok(item))) => item,
+                        Ok(None) => break,
+                        // failed or stalled while downloading, resume from the bytes on disk.
+                        chunk => {
+                            let _ = file.flush().await;
                             if retry_request > 0 {
                                 retry_request -= 1;
-                                continue;
+                                info!(
+                                    target: RETRY,
+                                    file = fname,
+                                    downloaded,
+                                    stalled = chunk.is_err(),
+                                    "resume download"
+                                );
+                                self.events.emit(Event::FileWaiting {
+                                    url: path.clone(),
+                                    name: fname.to_string(),
+                                    wait: if chunk.is_err() {
+                                        Wait::Stalled
+                                    } else {
+                                        Wait::Reconnect
+                                    },
+                                    status: status.clone(),
+                                });
+                                drop(permit);
+                                sleep(Duration::from_secs(1)).await;
+                                continue 'request;
                             }
                             download_info.add_failed_file(path.clone());
-                            self.log_status(&url, fname, "failed").await?;
-                            break;
+                            self.log_status(url, fname, "failed").await;
+                            let reason = if chunk.is_err() {
+                                "stalled"
+                            } else {
+                                "connection lost"
+                            };
+                            self.emit_failed(status, &path, fname, reason);
+                            return Ok((FileStatus::Failed, None));
                         }
                     };
-                    let pb = mc
-                        .lock()
-                        .await
-                        .add(ProgressBar::new(total_size + downloaded));
-                    pb.set_style(ProgressStyle::default_bar()
-                .template("{msg}\n{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
-                .unwrap()
-                .progress_chars("#>-"));
-
-                    // this download was clompleted.
-                    if 190 == res.status() || 416 == res.status() {
-                        pb.was_done(status.total, status.queues, fname).await;
-                        break 'request;
+                    // stop at a chunk boundary, bytes written so far are kept.
+                    if self.is_stopping() {
+                        let _ = file.flush().await;
+                        download_info.add_interrupted_file(path.clone());
+                        self.log_status(url, fname, "interrupted").await;
+                        self.emit_interrupted(status, &path, fname);
+                        return Ok((
+                            FileStatus::Partial,
+                            Some(file_size.unwrap_or(0) + total_size),
+                        ));
                     }
-
-                    // prevent too many requests
-                    if StatusCode::TOO_MANY_REQUESTS == res.status() {
-                        pb.wait(status.total, status.queues, fname);
-                        tokio::time::sleep(Duration::from_secs(TOO_MANY_REQUESTS_DELAY_SEC)).await;
-                        continue;
-                    }
-                    // prevent bad gateway: wait 2 secs and re-download
-                    if StatusCode::OK != res.status() && StatusCode::PARTIAL_CONTENT != res.status()
-                    {
-                        if retry == 0 {
-                            pb.failed(status.total, status.queues, fname).await;
-                            download_info.add_failed_file(path.clone());
-                            self.log_status(&url, fname, "failed").await?;
-                            break;
-                        }
-                        pb.retry_with_wait(status.total, status.queues, fname, download_counter);
-                        retry -= 1;
-                        tokio::time::sleep(Duration::from_secs(ERROR_REQUEST_DELAY_SEC)).await;
-                        continue;
+                    self.bandwidth.consume(item.len()).await;
+                    if let Err(err) = file.write_all(&item).await {
+                        download_info.add_failed_file(path.to_string());
+                        self.log_status(url, fname, "failed").await;
+                        self.emit_failed(
+                            status,
+                            &path,
+                            fname,
+                            format!("cannot write file: {}", err),
+                        );
+                        return Ok((FileStatus::Failed, None));
                     }
 
-                    let download_counter_print = if download_counter > 1 {
-                        format!("[{}]", download_counter)
-                    } else {
-                        String::new()
-                    };
-                    pb.download(status.total, status.queues, &download_counter_print, fname);
-                    let mut stream = res.bytes_stream();
-
-                    #[allow(unused_labels)]
-                    'receive_item: while let Some(item) = stream.next().await {
-                        match item {
-                            Ok(item) => {
-                                if (file.write_all(&item).await).is_err() {
-                                    eprintln!("Failed writes bytes to file");
-                                    download_info.add_failed_file(path.to_string());
-                                    self.log_status(&url, fname, "failed").await?;
-                                    break;
-                                }
-
-                                let new = min(downloaded + (item.len() as u64), total_size);
-                                downloaded = new;
-                                pb.set_position(new);
-                            }
-                            Err(_) => {
-                                // failed while downloading.
-                                if retry_request > 0 {
-                                    retry_request -= 1;
-                                    pb.reconnect(
-                                        status.total,
-                                        status.queues,
-                                        &download_counter_print,
-                                        fname,
-                                    );
-                                    sleep(Duration::from_secs(1)).await;
-                                    continue;
-                                }
-                                download_info.add_failed_file(path.clone());
-                                pb.failed(status.total, status.queues, fname).await;
-                                break 'request;
+                    reservation.written(item.len() as u64);
+                    self.concurrency.report_bytes(item.len() as u64);
+                    let new = min(downloaded + (item.len() as u64), total_size);
+                    self.events.emit(Event::FileProgress {
+                        url: path.clone(),
+                        bytes: new - downloaded,
+                    });
+                    downloaded = new;
+
+                    if let Some(buf) = &mut head {
+                        buf.extend_from_slice(&item[..min(item.len(), SNIFF_BYTES - buf.len())]);
+                        if buf.len() == SNIFF_BYTES {
+                            invalid = check_magic(fname, buf).err();
+                            head = None;
+                            if invalid.is_some() {
+                                break;
                             }
                         }
                     }
+                }
+                // a body shorter than `SNIFF_BYTES`.
+                if let Some(buf) = head {
+                    invalid = check_magic(fname, &buf).err();
+                }
+                if let Some(reason) = invalid {
+                    let _ = file.flush().await;
+                    drop(file);
+                    self.reject_content(url, fname, path_to_file, &reason)
+                        .await?;
+                    download_info.add_failed_file(path.clone());
+                    self.log_status(url, fname, "failed").await;
+                    self.emit_failed(status, &path, fname, format!("invalid content, {}", reason));
+                    return Ok((FileStatus::Failed, None));
+                }
 
-                    download_info.add_file_size(total_size);
-                    download_info.add_success_file(1);
-                    self.log_status(&url, fname, "success").await?;
-                    let _ = file.flush().await.context("file.flush");
+                download_info.add_file_size(total_size);
+                download_info.add_host_bytes(&path, total_size);
+                download_info.add_success_file(1);
+                self.log_status(url, fname, "success").await;
+                let _ = file.flush().await.context("file.flush");
 
-                    pb.finish_with_clear(status.total, status.queues, fname)
-                        .await;
-                } else {
-                    if retry_request == 0 {
-                        download_info.add_failed_file(path.clone());
-                        self.log_status(&url, fname, "failed").await?;
-                        break;
-                    }
-                    retry_request -= 1;
-                    tokio::time::sleep(Duration::from_secs(ERROR_REQUEST_DELAY_SEC)).await;
-                    continue;
+                let size = file_size.unwrap_or(0) + total_size;
+                self.emit_finished(status, &path, fname, Some(size));
+                return Ok((FileStatus::Done, Some(size)));
+            } else {
+                if retry_request == 0 {
+                    download_info.add_failed_file(path.clone());
+                    self.log_status(url, fname, "failed").await;
+                    self.emit_failed(status, &path, fname, "request failed");
+                    return Ok((FileStatus::Failed, None));
                 }
-                // break;
+                retry_request -= 1;
+                drop(permit);
+                tokio::time::sleep(Duration::from_secs(ERROR_REQUEST_DELAY_SEC)).await;
+                continue;
             }
         }
+    }
 
-        Ok(download_info)
+    /// Run a segmented download and log its result.
+    async fn finish_segments(
+        &self,
+        file: SegmentedFile<'_>,
+        status: &StatusBar,
+        download_info: &mut DownloaderInfo,
+    ) -> anyhow::Result<(FileStatus, Option<u64>)> {
+        let SegmentedFile {
+            url,
+            path,
+            fname,
+            path_to_file,
+            total_size,
+        } = file;
+        let success = self
+            .download_segments(path, fname, path_to_file, total_size, status, download_info)
+            .await?;
+        let file_status = if success {
+            FileStatus::Done
+        } else if self.is_stopping() {
+            FileStatus::Partial
+        } else {
+            FileStatus::Failed
+        };
+        let log = match file_status {
+            FileStatus::Done => "success",
+            FileStatus::Partial => "interrupted",
+            _ => "failed",
+        };
+        self.log_status(url, fname, log).await;
+        Ok((file_status, Some(total_size)))
     }
 }
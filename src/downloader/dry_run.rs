@@ -0,0 +1,174 @@
+use anyhow::Context;
+use futures_util::{StreamExt, stream};
+use json::object;
+use reqwest::header::CONTENT_TYPE;
+use std::path::Path;
+
+use crate::category::{FilterResult, OTHER};
+
+use super::{
+    Downloader,
+    estimate::{file_name, header_size},
+    event::Event,
+    manifest::FileStatus,
+};
+
+/// A file which would be downloaded.
+#[derive(Clone, Debug)]
+pub struct PlannedFile {
+    pub post: String,
+    pub url: String,
+    /// destination path of the file.
+    pub path: String,
+    pub category: String,
+    pub size: Option<u64>,
+}
+
+/// Files a run would download, after every filter.
+#[derive(Clone, Debug, Default)]
+pub struct DryRun {
+    pub files: Vec<PlannedFile>,
+    /// url and reason of skipped files.
+    pub skipped: Vec<(String, String)>,
+    /// files already downloaded by an earlier run.
+    pub done: u64,
+}
+
+impl DryRun {
+    /// Write the file list as JSON when the path ends with `.json`, as CSV otherwise.
+    pub async fn export(&self, path: &str) -> anyhow::Result<()> {
+        let content = if path.ends_with(".json") {
+            let files = self
+                .files
+                .iter()
+                .map(|file| {
+                    object! {
+                        post: file.post.clone(),
+                        url: file.url.clone(),
+                        path: file.path.clone(),
+                        category: file.category.clone(),
+                        size: file.size,
+                    }
+                })
+                .collect::<Vec<_>>();
+            json::stringify_pretty(files, 1)
+        } else {
+            let mut content = "post,url,path,category,size\n".to_string();
+            for file in &self.files {
+                let row = [
+                    file.post.as_str(),
+                    file.url.as_str(),
+                    file.path.as_str(),
+                    file.category.as_str(),
+                    &file.size.map(|s| s.to_string()).unwrap_or_default(),
+                ]
+                .map(csv_field)
+                .join(",");
+                content.push_str(&row);
+                content.push('\n');
+            }
+            content
+        };
+        tokio::fs::write(path, content)
+            .await
+            .with_context(|| format!("Cannot write {}", path))
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl Downloader {
+    /// Resolve pages and posts and apply every filter, nothing is written.
+    ///
+    /// # Arguments
+    /// * `head` - fetch size and type of each file with a HEAD request, sizes
+    ///   known by the manifest are used otherwise.
+    pub async fn dry_run(&self, head: bool) -> anyhow::Result<DryRun> {
+        self.load_manifest().await;
+        self.resolve_posts().await?;
+
+        let posts = self.manifest.lock().await.posts().to_vec();
+        let mut dry_run = DryRun::default();
+        let mut files = Vec::new();
+        for post in posts {
+            for file in post.files {
+                let fname = file_name(&file.url);
+                let path = format!("{}/{}", self.outdir, fname);
+                if file.status == FileStatus::Done && Path::new(&path).exists() {
+                    dry_run.done += 1;
+                    continue;
+                }
+                if let FilterResult::Skip(reason) = self.filter.check_name(fname) {
+                    dry_run.skipped.push((file.url, reason));
+                    continue;
+                }
+                files.push(PlannedFile {
+                    post: post.id.clone(),
+                    category: self
+                        .filter
+                        .categories
+                        .of_file(fname)
+                        .unwrap_or(OTHER)
+                        .to_string(),
+                    url: file.url,
+                    path,
+                    size: file.size,
+                });
+            }
+        }
+
+        if head {
+            self.events
+                .emit(Event::FetchingSizes { files: files.len() });
+        }
+        let mut checked = stream::iter(files)
+            .map(|mut file| async move {
+                let fname = file_name(&file.url).to_string();
+                let res = if head && !self.is_stopping() {
+                    self.head(&file.url).await
+                } else {
+                    None
+                };
+                let content_type = res.as_ref().and_then(|res| {
+                    res.headers()
+                        .get(CONTENT_TYPE)
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string)
+                });
+                if let Some(res) = &res {
+                    file.size = header_size(res).or(file.size);
+                }
+                // files without extension are classified by their type when it is known.
+                if matches!(self.filter.check_name(&fname), FilterResult::Unknown)
+                    && let Some(content_type) = &content_type
+                {
+                    file.category = self
+                        .filter
+                        .categories
+                        .of_content_type(content_type)
+                        .to_string();
+                    if let Some(reason) = self.filter.check_content_type(Some(content_type)) {
+                        return Err((file.url, reason));
+                    }
+                }
+                if let Some(reason) = file.size.and_then(|size| self.size_filter(size)) {
+                    return Err((file.url, reason));
+                }
+                Ok(file)
+            })
+            .buffered(self.task_limit);
+        while let Some(file) = checked.next().await {
+            match file {
+                Ok(file) => dry_run.files.push(file),
+                Err(skipped) => dry_run.skipped.push(skipped),
+            }
+        }
+        Ok(dry_run)
+    }
+}
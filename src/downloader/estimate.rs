@@ -0,0 +1,285 @@
+use anyhow::anyhow;
+use futures_util::{StreamExt, stream};
+use reqwest::{Response, StatusCode, header::CONTENT_LENGTH};
+use size::Size;
+use std::path::Path;
+use tokio::{fs, sync::mpsc};
+
+use crate::{category::OTHER, declare::POST_QUEUE_SIZE, request};
+
+use super::{Downloader, event::Event, manifest::FileStatus, space::Reservation};
+
+/// Files and bytes of a single category.
+#[derive(Clone, Debug)]
+pub struct CategorySize {
+    pub name: String,
+    pub files: u64,
+    /// total size of the files whose size is known.
+    pub size: u64,
+    /// bytes which are not on disk yet.
+    pub remaining: u64,
+}
+
+/// Size of a download, computed from the sizes kept in the manifest.
+#[derive(Clone, Debug, Default)]
+pub struct Estimate {
+    pub categories: Vec<CategorySize>,
+    /// files whose size is not known.
+    pub unknown: u64,
+    /// free space on the filesystem of the output folder.
+    pub available: Option<u64>,
+}
+
+impl Estimate {
+    fn add(&mut self, name: String, size: Option<u64>, remaining: u64) {
+        let category = match self.categories.iter_mut().position(|c| c.name == name) {
+            Some(i) => &mut self.categories[i],
+            None => {
+                self.categories.push(CategorySize {
+                    name,
+                    files: 0,
+                    size: 0,
+                    remaining: 0,
+                });
+                self.categories.last_mut().unwrap()
+            }
+        };
+        category.files += 1;
+        category.size += size.unwrap_or(0);
+        category.remaining += remaining;
+        if size.is_none() {
+            self.unknown += 1;
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.categories.iter().map(|c| c.size).sum()
+    }
+
+    /// Bytes left to download.
+    pub fn remaining(&self) -> u64 {
+        self.categories.iter().map(|c| c.remaining).sum()
+    }
+}
+
+impl Downloader {
+    /// HEAD request of a file, `None` unless the status is OK.
+    pub async fn head(&self, url: &str) -> Option<Response> {
+        let res = {
+            let _permit = self.connections.acquire().await.ok()?;
+            self.request
+                .send_file(request::new(&self.request).ok()?.head(url))
+                .await
+                .ok()?
+        };
+        (res.status() == StatusCode::OK).then_some(res)
+    }
+
+    /// Size of a file from a HEAD request.
+    pub async fn head_size(&self, url: &str) -> Option<u64> {
+        self.head(url).await.and_then(|res| header_size(&res))
+    }
+
+    /// Register every post id in the manifest and fetch the files of unresolved posts.
+    pub(super) async fn resolve_posts(&self) -> anyhow::Result<()> {
+        // post ids are registered in the manifest, the channel is only drained.
+        let (sender, mut receiver) = mpsc::channel::<String>(POST_QUEUE_SIZE);
+        let drain = tokio::spawn(async move { while receiver.recv().await.is_some() {} });
+        self.fetch_post_id(sender).await?;
+        drain.await?;
+
+        let unresolved = self
+            .manifest
+            .lock()
+            .await
+            .posts()
+            .iter()
+            .filter(|post| self.refresh || !post.resolved)
+            .map(|post| post.id.clone())
+            .collect::<Vec<_>>();
+        self.events.emit(Event::ResolvingPosts {
+            posts: unresolved.len(),
+        });
+        let mut resolved = stream::iter(unresolved)
+            .map(|pid| {
+                let mut downloader = self.clone();
+                async move {
+                    let url = downloader.link.post_id(&pid);
+                    let files = downloader.get_posts_from_page(&url).await;
+                    (pid, files)
+                }
+            })
+            .buffer_unordered(self.task_limit);
+        while let Some((pid, files)) = resolved.next().await {
+            if let Ok(files) = files {
+                self.manifest.lock().await.set_files(&pid, &files);
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve every post and file size without downloading.
+    ///
+    /// Sizes come from the manifest or from HEAD requests, they are saved in
+    /// the manifest so the next run starts without fetching them again.
+    pub async fn estimate(&self) -> anyhow::Result<Estimate> {
+        fs::create_dir_all(&self.outdir).await?;
+        self.load_manifest().await;
+        self.resolve_sizes().await?;
+        Ok(self.summarize().await)
+    }
+
+    /// Resolve the posts of the loaded manifest and fetch the unknown sizes.
+    pub(super) async fn resolve_sizes(&self) -> anyhow::Result<()> {
+        self.resolve_posts().await?;
+
+        let unknown = self
+            .manifest
+            .lock()
+            .await
+            .posts()
+            .iter()
+            .flat_map(|post| {
+                post.files
+                    .iter()
+                    .filter(|file| {
+                        file.size.is_none() && self.name_filter(file_name(&file.url)).is_none()
+                    })
+                    .map(|file| (post.id.clone(), file.url.clone()))
+            })
+            .collect::<Vec<_>>();
+        self.events.emit(Event::FetchingSizes {
+            files: unknown.len(),
+        });
+        let mut sizes = stream::iter(unknown)
+            .map(|(pid, url)| async move {
+                if self.is_stopping() {
+                    return (pid, url, None);
+                }
+                let size = self.head_size(&url).await;
+                (pid, url, size)
+            })
+            .buffer_unordered(self.task_limit);
+        while let Some((pid, url, size)) = sizes.next().await {
+            if size.is_some() {
+                self.manifest.lock().await.set_size(&pid, &url, size);
+            }
+        }
+        drop(sizes);
+
+        self.manifest.lock().await.save(&self.outdir).await
+    }
+
+    /// Sizes of the files known by the manifest, per category.
+    pub async fn summarize(&self) -> Estimate {
+        let files = self
+            .manifest
+            .lock()
+            .await
+            .posts()
+            .iter()
+            .flat_map(|post| post.files.clone())
+            .collect::<Vec<_>>();
+        let mut estimate = Estimate::default();
+        for file in files {
+            let fname = file_name(&file.url);
+            if self.name_filter(fname).is_some()
+                || file
+                    .size
+                    .is_some_and(|size| self.size_filter(size).is_some())
+            {
+                continue;
+            }
+            let remaining = match file.size {
+                Some(_) if file.status == FileStatus::Done => 0,
+                Some(size) => {
+                    let path_to_file = Path::new(&self.outdir).join(fname);
+                    let on_disk = fs::metadata(path_to_file).await.map_or(0, |m| m.len());
+                    size.saturating_sub(on_disk)
+                }
+                None => 0,
+            };
+            let category = self.filter.categories.of_file(fname).unwrap_or(OTHER);
+            estimate.add(category.to_string(), file.size, remaining);
+        }
+        estimate.available = fs4::available_space(&self.outdir).ok();
+        estimate
+    }
+
+    /// Fail when `needed` bytes do not fit on the filesystem of the output folder,
+    /// next to the bytes still to be written by the files in progress.
+    pub fn check_space(&self, needed: u64) -> anyhow::Result<()> {
+        self.reserve_space(needed).map(drop)
+    }
+
+    fn reserve_space(&self, needed: u64) -> anyhow::Result<Reservation> {
+        let available = match fs4::available_space(&self.outdir) {
+            Ok(available) if !self.force => available,
+            _ => return Ok(self.space.reserve_unchecked(needed)),
+        };
+        self.space.reserve(needed, available).map_err(|total| {
+            anyhow!(
+                "Not enough free space in {}: {} needed, {} available, use --force to download anyway",
+                self.outdir,
+                Size::from_bytes(total),
+                Size::from_bytes(available)
+            )
+        })
+    }
+
+    /// Reserve the bytes of a file for the time of its download, `None` when
+    /// they would not fit on the disk.
+    ///
+    /// Only this run is stopped, with an error, other runs sharing the
+    /// shutdown of `sync` or `watch` go on.
+    pub(super) async fn ensure_space(&self, needed: u64) -> Option<Reservation> {
+        match self.reserve_space(needed) {
+            Ok(reservation) => Some(reservation),
+            Err(err) => {
+                if !self.is_stopping() {
+                    self.info.lock().await.set_error(err.to_string());
+                    self.events.emit(Event::Stopped {
+                        reason: err.to_string(),
+                    });
+                    self.stop.request();
+                }
+                None
+            }
+        }
+    }
+}
+
+pub(super) fn file_name(url: &str) -> &str {
+    url.split("/").last().unwrap_or(url)
+}
+
+/// `content_length()` is always zero for HEAD, read the header instead.
+pub(super) fn header_size(res: &Response) -> Option<u64> {
+    res.headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{declare::Exit, link::Link, utils::Shutdown};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn full_disk_fails_only_its_own_run() {
+        let shutdown = Arc::new(Shutdown::new());
+        let link = Link::parse("https://coomer.st/onlyfans/user/someone".to_string()).unwrap();
+        let downloader = Downloader::builder(link)
+            .outdir(std::env::temp_dir().to_str().unwrap())
+            .shutdown(shutdown.clone())
+            .build();
+
+        assert!(downloader.ensure_space(u64::MAX).await.is_none());
+        assert!(downloader.is_stopping());
+        assert!(!shutdown.is_requested(), "other runs go on");
+        assert_eq!(downloader.exit().await, Exit::Failure);
+        assert_eq!(downloader.report().await["interrupted"], false);
+    }
+}
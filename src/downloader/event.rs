@@ -0,0 +1,163 @@
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::declare::Exit;
+
+use super::page_status::StatusBar;
+
+/// Why a file download waits before its next request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Wait {
+    /// the server answered 429.
+    TooManyRequests { secs: u64 },
+    /// the server answered with an error status, the request is sent again.
+    Retry {
+        status: u16,
+        attempt: u64,
+        secs: u64,
+    },
+    /// no bytes arrived for the stall timeout, the download is resumed.
+    Stalled,
+    /// the connection was closed, the download is resumed.
+    Reconnect,
+}
+
+/// Progress of a run, given to every handler of `Events`.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// the job saved in the output folder is continued without fetching pages.
+    Resumed {
+        posts: usize,
+    },
+    /// pages are fetched, post ids are sent to the workers as each page is parsed.
+    FetchingPages,
+    ProfileFetched {
+        url: String,
+        posts: u64,
+    },
+    /// the profile cannot be read, pages are walked one after another.
+    ProfileUnavailable {
+        url: String,
+        status: u16,
+    },
+    PageFetched {
+        url: String,
+        posts: usize,
+    },
+    /// `status` is none when no response or no valid JSON was received.
+    PageRetry {
+        url: String,
+        status: Option<u16>,
+    },
+    PageFailed {
+        url: String,
+    },
+    /// files of posts resolved for `--estimate`, `--dry-run` or the space check before a download.
+    ResolvingPosts {
+        posts: usize,
+    },
+    /// sizes fetched with HEAD requests for `--estimate`, `--dry-run` or the space check before a download.
+    FetchingSizes {
+        files: usize,
+    },
+    PostResolved {
+        post: String,
+        files: usize,
+    },
+    /// the files of the post could not be resolved.
+    PostFailed {
+        post: String,
+        url: String,
+    },
+    /// a request of the file was answered, `downloaded` bytes are already on disk.
+    FileStarted {
+        url: String,
+        name: String,
+        size: u64,
+        downloaded: u64,
+        attempt: u64,
+        /// number of parallel connections, 1 unless the file is split.
+        segments: usize,
+        status: StatusBar,
+    },
+    /// `bytes` more bytes of the file were written.
+    FileProgress {
+        url: String,
+        bytes: u64,
+    },
+    /// sent before `FileStarted` too, when the first answers were 429 or an error.
+    FileWaiting {
+        url: String,
+        name: String,
+        wait: Wait,
+        status: StatusBar,
+    },
+    FileFinished {
+        post: String,
+        url: String,
+        name: String,
+        size: Option<u64>,
+    },
+    FileSkipped {
+        post: String,
+        url: String,
+        name: String,
+        reason: String,
+    },
+    FileFailed {
+        post: String,
+        url: String,
+        name: String,
+        reason: String,
+    },
+    /// stopped by Ctrl+C, the file is resumed by the next run.
+    FileInterrupted {
+        post: String,
+        url: String,
+        name: String,
+    },
+    /// number of active workers of `--adaptive`, with the throughput in bytes per second.
+    Concurrency {
+        level: usize,
+        max: usize,
+        throughput: u64,
+    },
+    /// the run stops before the disk runs out.
+    Stopped {
+        reason: String,
+    },
+    Finished {
+        exit: Exit,
+    },
+}
+
+type Handler = Arc<dyn Fn(&Event) + Send + Sync>;
+
+/// Handlers called for every event of a run, in the task which sends it.
+///
+/// Handlers must return quickly, they run between two chunks of a download.
+#[derive(Clone, Default)]
+pub struct Events {
+    handlers: Vec<Handler>,
+}
+
+impl Events {
+    pub fn subscribe(&mut self, handler: impl Fn(&Event) + Send + Sync + 'static) {
+        self.handlers.push(Arc::new(handler));
+    }
+
+    /// Receive the events through a channel, e.g. to forward them from another task.
+    pub fn channel(&mut self) -> mpsc::UnboundedReceiver<Event> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.subscribe(move |event| {
+            let _ = sender.send(event.clone());
+        });
+        receiver
+    }
+
+    pub fn emit(&self, event: Event) {
+        for handler in &self.handlers {
+            handler(&event);
+        }
+    }
+}
@@ -1,71 +1,297 @@
-use std::{thread, time::Duration};
+use std::time::Duration;
 
-use crate::{declare, link::Page, request};
+use crate::{
+    declare::{self, PAGE_SIZE},
+    link::{Page, UrlType},
+    logging::{API, RETRY},
+};
 use anyhow::Result;
-use colored::Colorize;
+use chrono::NaiveDate;
+use futures_util::{StreamExt, stream};
 use reqwest::StatusCode;
+use tokio::{sync::mpsc::Sender, time::sleep};
+use tracing::{info, warn};
 
-use super::Downloader;
+use super::{Downloader, event::Event};
+
+/// Post ids of a single page.
+struct PageIds {
+    /// posts published on or after `since`.
+    ids: Vec<String>,
+    /// title of each post of `ids`.
+    titles: Vec<String>,
+    /// number of posts of the page, older ones included.
+    posts: usize,
+    /// the page has posts older than `since`, the next pages only have older ones.
+    older: bool,
+}
 
 impl Downloader {
-    /// This function will collect all post id from page(s).
-    pub async fn fetch_page(&self) -> Result<Vec<String>> {
-        let mut posts_id = Vec::new();
-        let mut confirm = 0;
-        let mut link = self.link.clone();
-        if let Page::All = link.page {
-            link.set_page(0);
+    /// Get the number of posts from the creator profile.
+    ///
+    /// Returns `None` when the profile cannot be read, pages are then walked
+    /// one after another instead.
+    pub async fn fetch_post_count(&self) -> Option<u64> {
+        let url = self.link.profile();
+        let res = self.api_get(&url).await.ok()?;
+        if res.status() != StatusCode::OK {
+            self.events.emit(Event::ProfileUnavailable {
+                url,
+                status: res.status().as_u16(),
+            });
+            return None;
         }
+        let obj = json::parse(&res.text().await.ok()?).ok()?;
 
-        let mut retry = self.retry;
-        println!("Start fetching pages");
+        if self.creator_name.lock().await.is_none()
+            && let Some(creator_name) = obj["name"].as_str()
+        {
+            *self.creator_name.lock().await = Some(creator_name.to_string());
+        }
+        let post_count = obj["post_count"].as_u64()?;
+        self.events.emit(Event::ProfileFetched {
+            url,
+            posts: post_count,
+        });
+        Some(post_count)
+    }
 
-        // Fetch all post IDs from paginated API
+    /// Fetch post ids of a single page.
+    async fn fetch_page_ids(&self, url: String) -> Result<PageIds> {
+        let mut retry = self.retry;
         loop {
-            print!("fetching {}", link.url().purple());
-
-            let client = request::new()?;
-            match client.get(link.url()).send().await {
-                Ok(r) => {
-                    if r.status() != StatusCode::OK {
-                        confirm += 1;
-                        if confirm < 3 {
-                            println!(" -- {} {}", "CONFIRM".yellow(), confirm);
-                            continue;
-                        }
-                        println!(" -- {}", "NONE".yellow().bold());
-                        break;
+            let status = match self.api_get(&url).await {
+                Ok(r) if r.status() == StatusCode::OK => {
+                    let content = r.text().await.unwrap_or_default();
+                    if let Ok(obj) = json::parse(&content) {
+                        let page = self.page_ids(&obj);
+                        self.events.emit(Event::PageFetched {
+                            url,
+                            posts: page.posts,
+                        });
+                        return Ok(page);
                     }
-
-                    confirm = 0;
-
-                    if let Ok(content) = r.text().await {
-                        if let Ok(obj) = json::parse(&content) {
-                            posts_id.extend((0..obj.len()).map(|i| obj[i]["id"].to_string()));
-                        } else {
-                            if retry > 0 {
-                                println!(" -- {} {}", "RETRY PARSE JSON".yellow(), confirm);
-                                retry -= 1;
-                                thread::sleep(Duration::from_secs(
-                                    declare::TOO_MANY_REQUESTS_DELAY_SEC,
-                                ));
-                                continue;
-                            }
-                            println!("Cannot parse JSON: {}", content);
-                        }
-                    }
-                    println!(" -- {}", "PASS".green().bold());
+                    None
                 }
-                Err(_) => {
-                    println!(" -- {}", "FAILED".red().bold());
-                    return Err(anyhow::anyhow!("Failed to fetch page"));
+                Ok(r) => Some(r.status().as_u16()),
+                Err(_) => None,
+            };
+            if retry == 0 {
+                warn!(target: API, url, "failed to fetch page");
+                self.events.emit(Event::PageFailed { url });
+                return Err(anyhow::anyhow!("Failed to fetch page"));
+            }
+            info!(target: RETRY, url, retry, "retry page");
+            retry -= 1;
+            self.events.emit(Event::PageRetry {
+                url: url.clone(),
+                status,
+            });
+            sleep(Duration::from_secs(declare::TOO_MANY_REQUESTS_DELAY_SEC)).await;
+        }
+    }
+
+    /// Post ids of a page, posts published before `since` are left out.
+    fn page_ids(&self, obj: &json::JsonValue) -> PageIds {
+        let mut page = PageIds {
+            ids: Vec::new(),
+            titles: Vec::new(),
+            posts: obj.len(),
+            older: false,
+        };
+        for post in obj.members() {
+            let published = post["published"]
+                .as_str()
+                .and_then(|date| date.get(..10))
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+            if let (Some(since), Some(published)) = (self.since, published)
+                && published < since
+            {
+                page.older = true;
+                continue;
+            }
+            page.ids.push(post["id"].to_string());
+            page.titles
+                .push(post["title"].as_str().unwrap_or_default().to_string());
+        }
+        page
+    }
+
+    /// Keep the posts of a page which are not in the manifest yet, before
+    /// they are sent to the workers.
+    ///
+    /// Nothing is new on the first run of a folder, every post would be.
+    async fn add_new_posts(&self, page: &PageIds) {
+        let new_posts = {
+            let manifest = self.manifest.lock().await;
+            if !manifest.loaded {
+                return;
+            }
+            page.ids
+                .iter()
+                .zip(&page.titles)
+                .filter(|(id, _)| manifest.post(id).is_none())
+                .map(|(id, title)| (id.clone(), title.clone()))
+                .collect::<Vec<_>>()
+        };
+        let mut info = self.info.lock().await;
+        for (id, title) in new_posts {
+            info.add_new_post(id, title);
+        }
+    }
+
+    /// Send post ids to the workers, returns `false` when all workers are gone.
+    ///
+    /// Posts are added to the manifest, posts finished by an earlier run are
+    /// not sent again.
+    pub async fn send_ids(&self, sender: &Sender<String>, ids: Vec<String>) -> bool {
+        let ids = {
+            let mut manifest = self.manifest.lock().await;
+            ids.into_iter()
+                .filter(|id| {
+                    manifest.add_post(id);
+                    !manifest.post(id).is_some_and(|post| post.is_done())
+                })
+                .collect::<Vec<_>>()
+        };
+        self.post_counter.add(ids.len() as u32);
+        for id in ids {
+            if sender.send(id).await.is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Ids of the first page which are not in the manifest, the posts published
+    /// since the last run.
+    ///
+    /// Only a single request is sent, a creator without manifest has all its
+    /// posts new.
+    pub async fn poll(&self) -> Result<Vec<String>> {
+        self.load_manifest().await;
+        let ids = match self.link.typ {
+            UrlType::Post => self
+                .link
+                .get_post_id()
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            _ => {
+                let mut link = self.link.clone();
+                link.set_page(0);
+                self.fetch_page_ids(link.url()).await?.ids
+            }
+        };
+        let manifest = self.manifest.lock().await;
+        Ok(ids
+            .into_iter()
+            .filter(|id| manifest.post(id).is_none())
+            .collect())
+    }
+
+    /// This function will send all post id from page(s) to the workers.
+    ///
+    /// Post ids are sent as soon as each page is parsed, so downloading can
+    /// start before the last page is fetched. Returns `true` when every page
+    /// was listed.
+    pub async fn fetch_page(&self, sender: &Sender<String>) -> Result<bool> {
+        self.events.emit(Event::FetchingPages);
+
+        if let Page::One(_) = self.link.page {
+            let page = self.fetch_page_ids(self.link.url()).await?;
+            self.add_new_posts(&page).await;
+            self.send_ids(sender, page.ids).await;
+            return Ok(true);
+        }
+
+        let mut link = self.link.clone();
+        link.set_page(0);
+
+        // pages are walked one after another to stop at the first known post.
+        let post_count = match self.until_known {
+            true => None,
+            false => self.fetch_post_count().await,
+        };
+        if let Some(post_count) = post_count {
+            // all offsets are known, fetch them concurrently but keep the order.
+            let mut pages = stream::iter(0..post_count.div_ceil(PAGE_SIZE))
+                .map(|page| {
+                    let mut link = link.clone();
+                    link.set_page(page);
+                    self.fetch_page_ids(link.url())
+                })
+                .buffered(self.api_task);
+            while let Some(page) = pages.next().await {
+                let page = page?;
+                self.add_new_posts(&page).await;
+                if !self.send_ids(sender, page.ids).await || page.older {
+                    return Ok(false);
                 }
             }
-            if let Page::One(_) = self.link.page {
-                break;
+            return Ok(true);
+        }
+
+        // Fetch all post IDs from paginated API, a short page is the last one.
+        loop {
+            let page = self.fetch_page_ids(link.url()).await?;
+            // older posts than a known one were listed by an earlier run.
+            let known = self.until_known && {
+                let manifest = self.manifest.lock().await;
+                page.ids.iter().any(|id| manifest.post(id).is_some())
+            };
+            let last_page = (page.posts as u64) < PAGE_SIZE;
+            self.add_new_posts(&page).await;
+            if !self.send_ids(sender, page.ids).await || page.older || known {
+                return Ok(false);
+            }
+            if last_page {
+                return Ok(true);
             }
             link.page_increst();
         }
-        Ok(posts_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::Link;
+
+    fn page(ids: &[&str]) -> PageIds {
+        PageIds {
+            ids: ids.iter().map(|id| id.to_string()).collect(),
+            titles: ids.iter().map(|id| format!("post {}", id)).collect(),
+            posts: ids.len(),
+            older: false,
+        }
+    }
+
+    fn downloader() -> Downloader {
+        Downloader::builder(Link::parse("https://kemono.cr/fanbox/user/1".to_string()).unwrap())
+            .outdir(std::env::temp_dir().to_str().unwrap())
+            .build()
+    }
+
+    #[tokio::test]
+    async fn no_new_posts_on_the_first_run() {
+        let downloader = downloader();
+        downloader.add_new_posts(&page(&["1", "2"])).await;
+        assert!(downloader.info.lock().await.get_new_post().is_empty());
+    }
+
+    #[tokio::test]
+    async fn new_posts_are_missing_from_the_manifest() {
+        let downloader = downloader();
+        {
+            let mut manifest = downloader.manifest.lock().await;
+            manifest.loaded = true;
+            manifest.add_post("1");
+        }
+        downloader.add_new_posts(&page(&["1", "2"])).await;
+        assert_eq!(
+            downloader.info.lock().await.get_new_post(),
+            [("2".to_string(), "post 2".to_string())]
+        );
     }
 }
@@ -1,12 +1,15 @@
-use std::{
-    thread::{self},
-    time::Duration,
-};
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use json::JsonValue;
+use reqwest::StatusCode;
+use tokio::time::sleep;
 
-use crate::{declare, request};
+use crate::{
+    declare,
+    logging::{API, RETRY},
+};
+use tracing::{info, warn};
 
 use super::Downloader;
 
@@ -23,21 +26,50 @@ impl Downloader {
         let mut json_parse_retry = self.retry;
         let mut http_retry = self.retry;
         loop {
-            let client = request::new()?;
-            let res = match client.get(url).send().await {
+            let res = match self.api_get(url).await {
                 Ok(v) => v,
                 Err(_) => {
-                    if !http_retry == 0 {
+                    if http_retry > 0 {
+                        info!(target: RETRY, url, retry = http_retry, "retry post");
                         http_retry -= 1;
-                        thread::sleep(Duration::from_secs(declare::ERROR_REQUEST_DELAY_SEC));
+                        sleep(Duration::from_secs(declare::ERROR_REQUEST_DELAY_SEC)).await;
                         continue;
                     }
+                    warn!(target: API, url, "failed to fetch post");
                     return Err(anyhow::anyhow!(
                         "Failed http request in `get_posts_from_page`"
                     ));
                 }
             };
 
+            // the shared limiter already slowed down, just wait for the next turn.
+            if res.status() == StatusCode::TOO_MANY_REQUESTS {
+                if http_retry == 0 {
+                    warn!(target: API, url, "too many requests, giving up on post");
+                    return Err(anyhow::anyhow!(
+                        "Too many requests in `get_posts_from_page`"
+                    ));
+                }
+                info!(target: RETRY, url, retry = http_retry, "too many requests");
+                http_retry -= 1;
+                sleep(Duration::from_secs(declare::TOO_MANY_REQUESTS_DELAY_SEC)).await;
+                continue;
+            }
+            // an error page is not an empty post, it is fetched again by the next run.
+            if !res.status().is_success() {
+                if http_retry > 0 {
+                    info!(target: RETRY, url, status = res.status().as_u16(), retry = http_retry, "retry post");
+                    http_retry -= 1;
+                    sleep(Duration::from_secs(declare::ERROR_REQUEST_DELAY_SEC)).await;
+                    continue;
+                }
+                warn!(target: API, url, status = res.status().as_u16(), "failed to fetch post");
+                return Err(anyhow::anyhow!(
+                    "HTTP {} in `get_posts_from_page`",
+                    res.status()
+                ));
+            }
+
             let text = res
                 .text()
                 .await
@@ -46,12 +78,13 @@ impl Downloader {
                 Ok(v) => v,
                 Err(_) => {
                     if json_parse_retry > 0 {
+                        info!(target: RETRY, url, retry = json_parse_retry, "retry invalid JSON");
                         json_parse_retry -= 1;
-                        thread::sleep(Duration::from_secs(declare::TOO_MANY_REQUESTS_DELAY_SEC));
+                        sleep(Duration::from_secs(declare::TOO_MANY_REQUESTS_DELAY_SEC)).await;
                         continue;
-                    } else {
-                        break;
                     }
+                    warn!(target: API, url, "invalid JSON for post");
+                    return Err(anyhow::anyhow!("Invalid JSON in `get_posts_from_page`"));
                 }
             };
 
@@ -78,7 +111,10 @@ impl Downloader {
                     // Some of videos could not be download, so it will be skipped.
                     if preview["server"].is_null() || preview["path"].is_null() {
                         if is_skip {
-                            self.info.lock().await.add_skip_file(url.to_string());
+                            self.info
+                                .lock()
+                                .await
+                                .add_skip_file(url.to_string(), "no file on server");
                         }
                     } else {
                         posts.push(format!("{}/data{}", preview["server"], preview["path"]));
@@ -1,15 +1,30 @@
 use crate::{
-    declare::{RetryType, TaskType},
-    link::Link,
+    category::FileFilter,
+    declare::{POST_QUEUE_SIZE, RetryType, TaskType},
+    link::{Link, Page},
+    logging::DOWNLOAD,
+    request::RequestOptions,
+    utils::{ApiLimiter, Bandwidth, Shutdown},
 };
 use anyhow::{Context, Result};
-use colored::Colorize;
+use chrono::NaiveDate;
 use futures_util::lock::Mutex;
-use indicatif::MultiProgress;
 use std::sync::Arc;
-use tokio::fs;
+use tokio::{
+    fs,
+    sync::{Semaphore, mpsc},
+};
+use tracing::warn;
 
-use super::{info::DownloaderInfo, page_status::StatusBar};
+use super::{
+    builder::DownloaderBuilder,
+    concurrency::Concurrency,
+    event::{Event, Events},
+    info::DownloaderInfo,
+    manifest::Manifest,
+    page_status::PostCounter,
+    space::DiskSpace,
+};
 
 #[derive(Clone)]
 pub struct Downloader {
@@ -19,93 +34,241 @@ pub struct Downloader {
     pub retry: RetryType,
     pub video_only: bool,
     pub image_only: bool,
-    pub multi_progress: Arc<Mutex<MultiProgress>>,
+    /// handlers of the progress of the run.
+    pub events: Events,
     pub info: Arc<Mutex<DownloaderInfo>>,
     pub verbose: bool,
     pub creator_name: Arc<Mutex<Option<String>>>,
+    pub post_counter: Arc<PostCounter>,
+    /// number of byte ranges a large file is split into.
+    pub segments: usize,
+    /// files larger than this size are downloaded in segments.
+    pub segment_threshold: u64,
+    /// global limit of open download connections, shared by all tasks.
+    pub connections: Arc<Semaphore>,
+    /// global bandwidth limiter, shared by all tasks.
+    pub bandwidth: Arc<Bandwidth>,
+    /// requests per second limiter for API endpoints, shared by all tasks.
+    pub api_limiter: Arc<ApiLimiter>,
+    /// number of API pages fetched at the same time.
+    pub api_task: usize,
+    /// number of workers allowed to download at the same time.
+    pub concurrency: Arc<Concurrency>,
+    /// set by Ctrl+C, workers stop taking new posts.
+    pub shutdown: Arc<Shutdown>,
+    /// set when this run stops on an error, e.g. a full disk, other runs
+    /// sharing `shutdown` go on.
+    pub stop: Arc<Shutdown>,
+    /// resolved posts and file status, saved in the output folder.
+    pub manifest: Arc<Mutex<Manifest>>,
+    /// fetch pages and unfinished posts again instead of using the manifest.
+    pub refresh: bool,
+    /// proxy, headers and cookies of every request.
+    pub request: RequestOptions,
+    /// download even when the files would not fit on the disk.
+    pub force: bool,
+    /// bytes still to be written by the files in progress, shared by all tasks.
+    pub space: Arc<DiskSpace>,
+    /// files smaller than this are skipped.
+    pub min_size: Option<u64>,
+    /// files larger than this are skipped.
+    pub max_size: Option<u64>,
+    /// selects files by category and extension.
+    pub filter: Arc<FileFilter>,
+    /// only posts published on or after this day are downloaded.
+    pub since: Option<NaiveDate>,
+    /// stop fetching pages at the first post of the manifest, only new posts are listed.
+    pub until_known: bool,
 }
 
 impl Downloader {
-    pub fn print_parameters(&self) {
-        println!("{}", "Parameters".green().bold());
-        println!("{} {}", "Link".blue().bold(), self.link.url());
-        println!("{} {}", "Outdir".blue().bold(), self.outdir);
-        println!("{} {}", "TaskLimit".blue().bold(), self.task_limit);
-        println!("{} {}", "Retry".blue().bold(), self.retry);
-        println!("{} {}", "VideoOnly".blue().bold(), self.video_only);
-        println!("{} {}", "ImageOnly".blue().bold(), self.image_only);
-        println!("{} {}", "Verbose".blue().bold(), self.verbose);
-        println!();
+    /// Settings of a new downloader of `link`.
+    pub fn builder(link: Link) -> DownloaderBuilder {
+        DownloaderBuilder::new(link)
+    }
+
+    /// Select files by category and extension, `video_only` and `image_only` are added to `only`.
+    pub fn set_filter(&mut self, mut filter: FileFilter) {
+        for (enabled, category) in [(self.video_only, "video"), (self.image_only, "image")] {
+            if enabled && !filter.only.iter().any(|c| c == category) {
+                filter.only.push(category.to_string());
+            }
+        }
+        self.filter = Arc::new(filter);
+    }
+
+    /// Scale the number of active tasks on server feedback, `task_limit` is the maximum.
+    pub fn set_adaptive(&mut self, adaptive: bool) {
+        self.concurrency = Arc::new(Concurrency::new(self.task_limit, adaptive));
+    }
+
+    /// Use the workers, connections and rate limits of `other`, so both runs share them.
+    pub fn share_limits(&mut self, other: &Downloader) {
+        self.connections = other.connections.clone();
+        self.bandwidth = other.bandwidth.clone();
+        self.api_limiter = other.api_limiter.clone();
+        self.concurrency = other.concurrency.clone();
+        self.space = other.space.clone();
+        self.shutdown = other.shutdown.clone();
     }
-    pub fn new(
-        link: Link,
-        task_limit: TaskType,
-        outdir: String,
-        retry: RetryType,
-        video_only: bool,
-        image_only: bool,
-        verbose: bool,
-    ) -> Self {
-        Self {
-            link,
-            task_limit,
-            outdir,
-            retry,
-            video_only,
-            image_only,
-            multi_progress: Arc::new(Mutex::from(MultiProgress::new())),
-            info: Arc::new(Mutex::new(DownloaderInfo::new())),
-            verbose,
-            creator_name: Arc::new(Mutex::new(None)),
+
+    /// The run has to stop, on Ctrl+C or on an error of its own.
+    pub fn is_stopping(&self) -> bool {
+        self.shutdown.is_requested() || self.stop.is_requested()
+    }
+
+    /// Resolves once the run has to stop.
+    pub async fn stopping(&self) {
+        tokio::select! {
+            _ = self.shutdown.wait() => {}
+            _ = self.stop.wait() => {}
         }
     }
 
-    /// Collect all posts id from sigle post or pages.
-    pub async fn fetch_post_id(&self) -> Result<Vec<String>> {
-        let posts_id = match self.link.typ {
+    /// Set the maximum number of download connections opened at the same time.
+    pub fn set_max_connections(&mut self, connections: usize) {
+        self.connections = Arc::new(Semaphore::new(connections.max(1)));
+    }
+
+    /// Send all posts id from sigle post or pages to the workers.
+    ///
+    /// An unfinished run is resumed from the post list of the manifest without
+    /// fetching pages. After a finished run pages are fetched again to find new
+    /// posts, the manifest only skips the posts already downloaded.
+    pub async fn fetch_post_id(&self, sender: mpsc::Sender<String>) -> Result<()> {
+        let pending = {
+            let manifest = self.manifest.lock().await;
+            (manifest.pages_complete && !manifest.finished && !self.refresh)
+                .then(|| manifest.pending_posts())
+                .filter(|pending| !pending.is_empty())
+        };
+        if let Some(pending) = pending {
+            self.events.emit(Event::Resumed {
+                posts: pending.len(),
+            });
+            self.send_ids(&sender, pending).await;
+            return Ok(());
+        }
+
+        let listed_all = match self.link.typ {
             crate::link::UrlType::Post => {
                 // Single post.
-                vec![self.link.get_post_id().expect("invalid url").to_string()]
+                let pid = self.link.get_post_id().expect("invalid url").to_string();
+                self.send_ids(&sender, vec![pid]).await;
+                true
             }
-            crate::link::UrlType::Page | crate::link::UrlType::None => self.fetch_page().await?,
+            crate::link::UrlType::Page | crate::link::UrlType::None => {
+                self.fetch_page(&sender).await?
+            }
+        };
+        // older posts were not listed, the next run needs all pages.
+        if !self.is_stopping() && listed_all {
+            self.manifest.lock().await.pages_complete = true;
+        }
+        Ok(())
+    }
+
+    /// Load the manifest of the output folder when it belongs to the same url.
+    pub async fn load_manifest(&self) {
+        let url = self.link.clear_option();
+        let page = match self.link.page {
+            Page::All => None,
+            Page::One(page) => Some(page),
+        };
+        let manifest = match Manifest::load(&self.outdir).await {
+            Some(manifest) if manifest.matches(&url, page) => manifest,
+            _ => Manifest::new(url, page),
         };
-        Ok(posts_id)
+        *self.manifest.lock().await = manifest;
     }
 
     /// Main function to download all content.
+    ///
+    /// Pages are fetched by a producer task which feeds the workers through a
+    /// bounded channel, so downloads start after the first page. An error is
+    /// kept in the info, `Event::Finished` is sent in every case.
     pub async fn all(&mut self) -> anyhow::Result<()> {
-        self.print_parameters();
+        let result = self.download_all().await;
+        if let Err(err) = &result {
+            self.info.lock().await.set_error(err.to_string());
+        }
+        self.events.emit(Event::Finished {
+            exit: self.exit().await,
+        });
+        result
+    }
 
-        let posts_id = self.fetch_post_id().await.context("Failed fetch post id")?;
-        let posts_id = Arc::new(Mutex::new(posts_id));
-        let posts_id_total = { posts_id.lock().await.len() };
+    async fn download_all(&mut self) -> anyhow::Result<()> {
+        self.info.lock().await.start();
         fs::create_dir_all(&self.outdir).await?;
+        self.load_manifest().await;
+        // a finished manifest is only missing new posts, fetching every page
+        // twice is not worth it. Every file is checked with the bytes in
+        // progress before it starts anyway.
+        let finished = self.manifest.lock().await.finished;
+        if !self.force && !self.refresh && !finished {
+            self.resolve_sizes().await?;
+            if self.is_stopping() {
+                return Ok(());
+            }
+        }
+        self.check_space(self.summarize().await.remaining())?;
+
+        let (sender, receiver) = mpsc::channel::<String>(POST_QUEUE_SIZE);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let producer = {
+            let self_instance = self.clone();
+            tokio::spawn(async move { self_instance.fetch_post_id(sender).await })
+        };
+
+        let events_id = self
+            .concurrency
+            .is_adaptive()
+            .then(|| self.concurrency.set_events(self.events.clone()));
 
         let mut multi_tasks = Vec::new();
 
         for _ in 0..self.task_limit {
             let mut self_instance = self.clone();
             let info = self.info.clone();
-            let posts_id = posts_id.clone();
+            let receiver = receiver.clone();
             multi_tasks.push(tokio::spawn(async move {
                 loop {
-                    let (pid, status) = {
-                        let mut posts_id = posts_id.lock().await;
-                        (
-                            posts_id.pop(),
-                            StatusBar {
-                                queues: posts_id.len() as u32,
-                                total: posts_id_total as u32,
-                            },
-                        )
+                    let concurrency = self_instance.concurrency.clone();
+                    let stopping = self_instance.clone();
+                    let pid = tokio::select! {
+                        pid = async { receiver.lock().await.recv().await } => pid,
+                        _ = stopping.stopping() => None,
                     };
-                    if let Some(pid) = pid {
-                        if let Ok(result) = self_instance.download_post(pid, status).await {
-                            info.lock().await.integrate(&result);
-                        }
-                    } else {
+                    let Some(pid) = pid else {
                         break;
+                    };
+                    // the slot is taken with a post in hand, workers waiting
+                    // for pages leave the slots shared by `sync` to others.
+                    let _permit = tokio::select! {
+                        permit = concurrency.acquire() => permit,
+                        _ = stopping.stopping() => break,
+                    };
+                    let status = self_instance.post_counter.take(&pid);
+                    match self_instance.download_post(pid.clone(), status).await {
+                        Ok(result) => info.lock().await.integrate(&result),
+                        // e.g. the file cannot be written, the post is downloaded again by the next run.
+                        Err(err) => {
+                            let url = self_instance.link.post_id(&pid).replace("api/v1/", "");
+                            warn!(target: DOWNLOAD, post = url, error = %err, "post failed");
+                            info.lock().await.add_failed_post(url.clone());
+                            self_instance
+                                .events
+                                .emit(Event::PostFailed { post: pid, url });
+                        }
                     }
+                    let _ = self_instance
+                        .manifest
+                        .lock()
+                        .await
+                        .save_later(&self_instance.outdir)
+                        .await;
                 }
             }));
         }
@@ -113,6 +276,26 @@ impl Downloader {
         for handle in multi_tasks {
             handle.await.unwrap()
         }
+        if let Some(id) = events_id {
+            self.concurrency.finish(id);
+        }
+
+        // pages left to fetch are not needed anymore.
+        if self.is_stopping() {
+            producer.abort();
+            self.info.lock().await.finish();
+            let mut manifest = self.manifest.lock().await;
+            manifest.finished = false;
+            manifest.save(&self.outdir).await?;
+            return Ok(());
+        }
+        let fetched = producer.await?.context("Failed fetch post id");
+        self.info.lock().await.finish();
+        let mut manifest = self.manifest.lock().await;
+        manifest.finished = fetched.is_ok();
+        manifest.save(&self.outdir).await?;
+        drop(manifest);
+        fetched?;
 
         Ok(())
     }
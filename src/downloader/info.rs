@@ -1,11 +1,55 @@
+use chrono::{DateTime, Local};
+use std::collections::BTreeMap;
+
+use crate::declare::Exit;
+
+use super::manifest::FileStatus;
+
+/// Result of a single file, written in the report.
+#[derive(Clone, Debug)]
+pub struct FileResult {
+    pub post: String,
+    pub url: String,
+    pub status: FileStatus,
+    /// status code of the last response, none when no response was received.
+    pub http_code: Option<u16>,
+    pub size: Option<u64>,
+}
+
+/// Requests sent to a single host.
+#[derive(Clone, Debug, Default)]
+pub struct HostStats {
+    pub requests: u64,
+    /// requests without response or answered with an error status.
+    pub errors: u64,
+    /// bytes of the files downloaded from the host.
+    pub bytes: u64,
+}
+
 #[derive(Clone)]
 pub struct DownloaderInfo {
     file_size: u64,
     success_file: u64,
     /// Some of files could not be download. So just skip it and manual download.
-    skip_file: Vec<String>,
+    /// Each url is kept with the reason it was skipped.
+    skip_file: Vec<(String, String)>,
     /// keeps failed downloaded url.
     failed_file: Vec<String>,
+    /// posts whose files could not be resolved.
+    failed_post: Vec<String>,
+    /// posts listed for the first time, with their title, none on the first run of a folder.
+    new_post: Vec<(String, String)>,
+    /// files stopped by Ctrl+C, they are resumed by the next run.
+    interrupted_file: Vec<String>,
+    /// every file handled by the run, in order.
+    files: Vec<FileResult>,
+    hosts: BTreeMap<String, HostStats>,
+    started: Option<DateTime<Local>>,
+    finished: Option<DateTime<Local>>,
+    /// error which stopped the run.
+    error: Option<String>,
+    /// status code of the last response of the file being downloaded.
+    http_code: Option<u16>,
 }
 
 impl DownloaderInfo {
@@ -15,6 +59,15 @@ impl DownloaderInfo {
             success_file: 0,
             skip_file: Vec::new(),
             failed_file: Vec::new(),
+            failed_post: Vec::new(),
+            new_post: Vec::new(),
+            interrupted_file: Vec::new(),
+            files: Vec::new(),
+            hosts: BTreeMap::new(),
+            started: None,
+            finished: None,
+            error: None,
+            http_code: None,
         }
     }
 
@@ -22,7 +75,18 @@ impl DownloaderInfo {
         self.file_size += dinfo.file_size;
         self.success_file += dinfo.success_file;
         self.failed_file.append(&mut dinfo.failed_file.clone());
+        self.failed_post.append(&mut dinfo.failed_post.clone());
+        self.new_post.append(&mut dinfo.new_post.clone());
         self.skip_file.append(&mut dinfo.skip_file.clone());
+        self.interrupted_file
+            .append(&mut dinfo.interrupted_file.clone());
+        self.files.append(&mut dinfo.files.clone());
+        for (host, stats) in &dinfo.hosts {
+            let entry = self.hosts.entry(host.clone()).or_default();
+            entry.requests += stats.requests;
+            entry.errors += stats.errors;
+            entry.bytes += stats.bytes;
+        }
     }
 
     pub fn get_file_size(&self) -> u64 {
@@ -33,7 +97,7 @@ impl DownloaderInfo {
         self.success_file
     }
 
-    pub fn get_skip_file(&self) -> Vec<String> {
+    pub fn get_skip_file(&self) -> Vec<(String, String)> {
         self.skip_file.clone()
     }
 
@@ -41,6 +105,38 @@ impl DownloaderInfo {
         self.failed_file.clone()
     }
 
+    pub fn get_failed_post(&self) -> Vec<String> {
+        self.failed_post.clone()
+    }
+
+    pub fn get_new_post(&self) -> &[(String, String)] {
+        &self.new_post
+    }
+
+    pub fn get_interrupted_file(&self) -> Vec<String> {
+        self.interrupted_file.clone()
+    }
+
+    pub fn get_files(&self) -> &[FileResult] {
+        &self.files
+    }
+
+    pub fn get_hosts(&self) -> &BTreeMap<String, HostStats> {
+        &self.hosts
+    }
+
+    pub fn get_started(&self) -> Option<DateTime<Local>> {
+        self.started
+    }
+
+    pub fn get_finished(&self) -> Option<DateTime<Local>> {
+        self.finished
+    }
+
+    pub fn get_error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
     pub fn add_file_size(&mut self, file_size: u64) {
         self.file_size += file_size;
     }
@@ -49,13 +145,85 @@ impl DownloaderInfo {
         self.success_file += success_file;
     }
 
-    pub fn add_skip_file(&mut self, url: String) {
-        self.skip_file.push(url);
+    pub fn add_skip_file(&mut self, url: String, reason: impl Into<String>) {
+        self.skip_file.push((url, reason.into()));
     }
 
     pub fn add_failed_file(&mut self, url: String) {
         self.failed_file.push(url);
     }
+
+    pub fn add_failed_post(&mut self, url: String) {
+        self.failed_post.push(url);
+    }
+
+    pub fn add_new_post(&mut self, id: String, title: String) {
+        self.new_post.push((id, title));
+    }
+
+    pub fn add_interrupted_file(&mut self, url: String) {
+        self.interrupted_file.push(url);
+    }
+
+    pub fn add_file_result(&mut self, result: FileResult) {
+        self.files.push(result);
+    }
+
+    pub fn set_http_code(&mut self, code: u16) {
+        self.http_code = Some(code);
+    }
+
+    /// Status code of the last response, reset for the next file.
+    pub fn take_http_code(&mut self) -> Option<u16> {
+        self.http_code.take()
+    }
+
+    /// Count a request to the host of `url`, `code` is none when no response was received.
+    pub fn add_request(&mut self, url: &str, code: Option<u16>) {
+        let stats = self.hosts.entry(host(url)).or_default();
+        stats.requests += 1;
+        if code.is_none_or(|code| code >= 400) {
+            stats.errors += 1;
+        }
+    }
+
+    pub fn add_host_bytes(&mut self, url: &str, bytes: u64) {
+        self.hosts.entry(host(url)).or_default().bytes += bytes;
+    }
+
+    pub fn start(&mut self) {
+        self.started = Some(Local::now());
+        self.finished = None;
+    }
+
+    pub fn finish(&mut self) {
+        self.finished = Some(Local::now());
+    }
+
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    /// Exit code of the run, `interrupted` when it was stopped by Ctrl+C.
+    pub fn exit(&self, interrupted: bool) -> Exit {
+        let failed = self.failed_file.len() + self.failed_post.len();
+        if interrupted {
+            Exit::Interrupted
+        } else if self.error.is_some() || (failed > 0 && self.success_file == 0) {
+            Exit::Failure
+        } else if failed > 0 {
+            Exit::Partial
+        } else {
+            Exit::Success
+        }
+    }
+}
+
+fn host(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default()
 }
 
 impl Default for DownloaderInfo {
@@ -63,3 +231,37 @@ impl Default for DownloaderInfo {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_of_a_run() {
+        let mut info = DownloaderInfo::new();
+        assert_eq!(info.exit(false), Exit::Success);
+        info.add_skip_file("a.zip".to_string(), "excluded");
+        assert_eq!(info.exit(false), Exit::Success);
+
+        info.add_failed_file("b.mp4".to_string());
+        assert_eq!(info.exit(false), Exit::Failure, "nothing downloaded");
+        info.add_success_file(1);
+        assert_eq!(info.exit(false), Exit::Partial);
+        assert_eq!(info.exit(true), Exit::Interrupted);
+
+        info.set_error("Not enough free space".to_string());
+        assert_eq!(info.exit(false), Exit::Failure);
+    }
+
+    #[test]
+    fn failed_posts_count_as_failures() {
+        let mut info = DownloaderInfo::new();
+        info.add_failed_post("post".to_string());
+        assert_eq!(info.exit(false), Exit::Failure);
+
+        let mut merged = DownloaderInfo::new();
+        merged.add_success_file(3);
+        merged.integrate(&info);
+        assert_eq!(merged.exit(false), Exit::Partial);
+    }
+}
@@ -0,0 +1,368 @@
+use anyhow::Context;
+use json::{JsonValue, object};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::fs;
+
+use crate::declare::{MANIFEST_FILE, MANIFEST_SAVE_INTERVAL_SEC};
+
+/// Download state of a single file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileStatus {
+    Pending,
+    Partial,
+    Done,
+    Failed,
+    Skipped,
+}
+
+impl FileStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileStatus::Pending => "pending",
+            FileStatus::Partial => "partial",
+            FileStatus::Done => "done",
+            FileStatus::Failed => "failed",
+            FileStatus::Skipped => "skipped",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "partial" => FileStatus::Partial,
+            "done" => FileStatus::Done,
+            "failed" => FileStatus::Failed,
+            "skipped" => FileStatus::Skipped,
+            _ => FileStatus::Pending,
+        }
+    }
+
+    /// Nothing left to do for this file.
+    ///
+    /// Skipped files are checked again, the filters of the next run may keep them.
+    pub fn is_finished(&self) -> bool {
+        *self == FileStatus::Done
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ManifestFile {
+    pub url: String,
+    pub status: FileStatus,
+    pub size: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ManifestPost {
+    pub id: String,
+    /// the file list was fetched from the API.
+    pub resolved: bool,
+    pub files: Vec<ManifestFile>,
+}
+
+impl ManifestPost {
+    pub fn is_done(&self) -> bool {
+        self.resolved && self.files.iter().all(|f| f.status.is_finished())
+    }
+}
+
+/// Job manifest kept in the output folder.
+///
+/// It keeps the resolved post list, the files of each post and their status,
+/// so an interrupted run continues without fetching pages and posts again.
+#[derive(Clone, Debug, Default)]
+pub struct Manifest {
+    /// API url of the creator or post, without page option.
+    pub url: String,
+    pub page: Option<u64>,
+    /// every page was fetched, the post list is complete.
+    pub pages_complete: bool,
+    /// the last run went through the whole post list, new posts are only
+    /// found by fetching pages again.
+    pub finished: bool,
+    /// read from the output folder, `false` on the first run of the folder.
+    pub loaded: bool,
+    posts: Vec<ManifestPost>,
+    index: HashMap<String, usize>,
+    last_save: Option<Instant>,
+}
+
+impl Manifest {
+    pub fn new(url: String, page: Option<u64>) -> Self {
+        Self {
+            url,
+            page,
+            ..Default::default()
+        }
+    }
+
+    pub fn path(outdir: &str) -> PathBuf {
+        Path::new(outdir).join(MANIFEST_FILE)
+    }
+
+    /// Url to pass to `Link::parse` when resuming without url.
+    pub fn web_url(&self) -> String {
+        self.url.replace("api/v1/", "")
+    }
+
+    pub fn matches(&self, url: &str, page: Option<u64>) -> bool {
+        self.url == url && self.page == page
+    }
+
+    /// Load the manifest of an output folder, `None` when missing or invalid.
+    pub async fn load(outdir: &str) -> Option<Self> {
+        let content = fs::read_to_string(Self::path(outdir)).await.ok()?;
+        let obj = json::parse(&content).ok()?;
+        let mut manifest = Self::new(obj["url"].as_str()?.to_string(), obj["page"].as_u64());
+        manifest.pages_complete = obj["pages_complete"].as_bool().unwrap_or(false);
+        manifest.finished = obj["finished"].as_bool().unwrap_or(false);
+        manifest.loaded = true;
+        for post in obj["posts"].members() {
+            let Some(id) = post["id"].as_str() else {
+                continue;
+            };
+            manifest.add_post(id);
+            let post_index = manifest.index[id];
+            let entry = &mut manifest.posts[post_index];
+            entry.resolved = post["resolved"].as_bool().unwrap_or(false);
+            entry.files = post["files"]
+                .members()
+                .filter_map(|file| {
+                    Some(ManifestFile {
+                        url: file["url"].as_str()?.to_string(),
+                        status: FileStatus::parse(file["status"].as_str().unwrap_or_default()),
+                        size: file["size"].as_u64(),
+                    })
+                })
+                .collect();
+        }
+        Some(manifest)
+    }
+
+    fn to_json(&self) -> JsonValue {
+        let posts = self
+            .posts
+            .iter()
+            .map(|post| {
+                let files = post
+                    .files
+                    .iter()
+                    .map(|file| {
+                        object! {
+                            url: file.url.clone(),
+                            status: file.status.as_str(),
+                            size: file.size,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                object! {
+                    id: post.id.clone(),
+                    resolved: post.resolved,
+                    files: files,
+                }
+            })
+            .collect::<Vec<_>>();
+        object! {
+            url: self.url.clone(),
+            page: self.page,
+            pages_complete: self.pages_complete,
+            finished: self.finished,
+            posts: posts,
+        }
+    }
+
+    /// Write the manifest, a temporary file is renamed so a crash never leaves half a file.
+    pub async fn save(&mut self, outdir: &str) -> anyhow::Result<()> {
+        let path = Self::path(outdir);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, self.to_json().pretty(1))
+            .await
+            .context("cannot write manifest")?;
+        fs::rename(&tmp, &path)
+            .await
+            .context("cannot write manifest")?;
+        self.last_save = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Save at most once per `MANIFEST_SAVE_INTERVAL_SEC`, used while downloading.
+    pub async fn save_later(&mut self, outdir: &str) -> anyhow::Result<()> {
+        if let Some(last) = self.last_save
+            && last.elapsed() < Duration::from_secs(MANIFEST_SAVE_INTERVAL_SEC)
+        {
+            return Ok(());
+        }
+        self.save(outdir).await
+    }
+
+    pub fn add_post(&mut self, id: &str) {
+        if !self.index.contains_key(id) {
+            self.index.insert(id.to_string(), self.posts.len());
+            self.posts.push(ManifestPost {
+                id: id.to_string(),
+                ..Default::default()
+            });
+        }
+    }
+
+    pub fn post(&self, id: &str) -> Option<&ManifestPost> {
+        self.index.get(id).map(|i| &self.posts[*i])
+    }
+
+    pub fn posts(&self) -> &[ManifestPost] {
+        &self.posts
+    }
+
+    /// Ids of posts which still have something to download.
+    pub fn pending_posts(&self) -> Vec<String> {
+        self.posts
+            .iter()
+            .filter(|post| !post.is_done())
+            .map(|post| post.id.clone())
+            .collect()
+    }
+
+    /// Set the file list of a post, status of already known files is kept.
+    pub fn set_files(&mut self, id: &str, urls: &[String]) {
+        self.add_post(id);
+        let post = &mut self.posts[self.index[id]];
+        let files = urls
+            .iter()
+            .map(|url| {
+                post.files
+                    .iter()
+                    .find(|file| &file.url == url)
+                    .cloned()
+                    .unwrap_or(ManifestFile {
+                        url: url.clone(),
+                        status: FileStatus::Pending,
+                        size: None,
+                    })
+            })
+            .collect();
+        post.files = files;
+        post.resolved = true;
+    }
+
+    pub fn file(&self, id: &str, url: &str) -> Option<&ManifestFile> {
+        self.post(id)?.files.iter().find(|file| file.url == url)
+    }
+
+    pub fn set_size(&mut self, id: &str, url: &str, size: Option<u64>) {
+        let Some(i) = self.index.get(id) else {
+            return;
+        };
+        if let Some(file) = self.posts[*i].files.iter_mut().find(|file| file.url == url) {
+            file.size = size;
+        }
+    }
+
+    pub fn set_status(&mut self, id: &str, url: &str, status: FileStatus, size: Option<u64>) {
+        let Some(i) = self.index.get(id) else {
+            return;
+        };
+        if let Some(file) = self.posts[*i].files.iter_mut().find(|file| file.url == url) {
+            file.status = status;
+            if size.is_some() {
+                file.size = size;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://kemono.cr/api/v1/fanbox/user/1";
+
+    fn urls(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn save_and_load_keep_the_state() {
+        let dir = std::env::temp_dir().join(format!("cktool-manifest-{}", std::process::id()));
+        fs::create_dir_all(&dir).await.unwrap();
+        let outdir = dir.to_str().unwrap();
+
+        let mut manifest = Manifest::new(URL.to_string(), Some(2));
+        manifest.pages_complete = true;
+        manifest.set_files("10", &urls(&["/a.jpg", "/b.zip"]));
+        manifest.set_status("10", "/a.jpg", FileStatus::Done, Some(42));
+        manifest.set_size("10", "/b.zip", Some(7));
+        manifest.add_post("11");
+        manifest.save(outdir).await.unwrap();
+
+        let loaded = Manifest::load(outdir).await;
+        fs::remove_dir_all(&dir).await.unwrap();
+        let loaded = loaded.unwrap();
+        assert!(loaded.matches(URL, Some(2)));
+        assert!(loaded.pages_complete);
+        assert!(!loaded.finished);
+        assert!(loaded.loaded);
+        assert_eq!(
+            loaded
+                .posts()
+                .iter()
+                .map(|post| &post.id)
+                .collect::<Vec<_>>(),
+            ["10", "11"]
+        );
+        let a = loaded.file("10", "/a.jpg").unwrap();
+        assert_eq!((a.status, a.size), (FileStatus::Done, Some(42)));
+        let b = loaded.file("10", "/b.zip").unwrap();
+        assert_eq!((b.status, b.size), (FileStatus::Pending, Some(7)));
+        assert!(loaded.post("10").unwrap().resolved);
+        assert!(!loaded.post("11").unwrap().resolved);
+        assert!(Manifest::load(outdir).await.is_none());
+    }
+
+    #[test]
+    fn matches_url_and_page() {
+        let manifest = Manifest::new(URL.to_string(), None);
+        assert!(manifest.matches(URL, None));
+        assert!(!manifest.matches(URL, Some(1)));
+        assert!(!manifest.matches("https://kemono.cr/api/v1/fanbox/user/2", None));
+        assert!(!Manifest::new(URL.to_string(), Some(1)).matches(URL, Some(3)));
+    }
+
+    #[test]
+    fn pending_posts_until_every_file_is_done() {
+        let mut manifest = Manifest::new(URL.to_string(), None);
+        manifest.add_post("1");
+        manifest.set_files("2", &urls(&["/a.jpg", "/b.jpg"]));
+        manifest.set_files("3", &[]);
+        assert!(
+            !manifest.post("1").unwrap().is_done(),
+            "files not fetched yet"
+        );
+        assert!(manifest.post("3").unwrap().is_done());
+        assert_eq!(manifest.pending_posts(), ["1", "2"]);
+
+        manifest.set_status("2", "/a.jpg", FileStatus::Done, None);
+        manifest.set_status("2", "/b.jpg", FileStatus::Skipped, None);
+        assert_eq!(
+            manifest.pending_posts(),
+            ["1", "2"],
+            "skipped files are checked again"
+        );
+        manifest.set_status("2", "/b.jpg", FileStatus::Done, None);
+        assert_eq!(manifest.pending_posts(), ["1"]);
+
+        // a new file list keeps the status of the known files.
+        manifest.set_files("2", &urls(&["/b.jpg", "/c.jpg"]));
+        assert_eq!(
+            manifest.file("2", "/b.jpg").unwrap().status,
+            FileStatus::Done
+        );
+        assert_eq!(
+            manifest.file("2", "/c.jpg").unwrap().status,
+            FileStatus::Pending
+        );
+        assert!(manifest.file("2", "/a.jpg").is_none());
+    }
+}
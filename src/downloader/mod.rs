@@ -1,12 +1,28 @@
 // private
+mod api;
+mod builder;
+mod concurrency;
 mod download_post;
+mod dry_run;
+mod estimate;
+mod event;
 mod fetch_pages;
 mod get_posts_from_page;
 mod index;
 mod info;
+mod manifest;
 mod page_status;
-mod print;
+mod report;
+mod segment;
+mod space;
 
 // public
+pub use builder::DownloaderBuilder;
+pub use dry_run::{DryRun, PlannedFile};
+pub use estimate::{CategorySize, Estimate};
+pub use event::{Event, Events, Wait};
 pub use index::Downloader;
-pub use page_status::PageStatus;
+pub use info::{FileResult, HostStats};
+pub use manifest::{FileStatus, Manifest};
+pub use page_status::{PageStatus, StatusBar};
+pub use report::write_report;
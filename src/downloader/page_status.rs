@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
 /// Represents pagination information for downloads
 #[derive(Clone)]
 pub struct PageStatus {
@@ -5,8 +7,37 @@ pub struct PageStatus {
     pub total: u32,
 }
 
-#[derive(Clone)]
+/// Post taken by a worker, with the posts of the run at that time.
+#[derive(Clone, Debug)]
 pub struct StatusBar {
+    pub post: String,
     pub queues: u32,
     pub total: u32,
 }
+
+/// Counts posts while pages are still being fetched.
+///
+/// `total` grows whenever a page is parsed, `taken` grows whenever a worker
+/// picks up a post, so the progress total keeps up with the producer.
+#[derive(Default)]
+pub struct PostCounter {
+    total: AtomicU32,
+    taken: AtomicU32,
+}
+
+impl PostCounter {
+    pub fn add(&self, posts: u32) {
+        self.total.fetch_add(posts, Ordering::SeqCst);
+    }
+
+    /// Mark one post as taken by a worker and return the current status.
+    pub fn take(&self, post: &str) -> StatusBar {
+        let taken = self.taken.fetch_add(1, Ordering::SeqCst) + 1;
+        let total = self.total.load(Ordering::SeqCst);
+        StatusBar {
+            post: post.to_string(),
+            queues: total.saturating_sub(taken),
+            total,
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use json::{JsonValue, object};
+
+use crate::declare::Exit;
+
+use super::Downloader;
+
+impl Downloader {
+    /// Failed posts, failed and interrupted files, all have to be downloaded again.
+    pub async fn failed_file(&self) -> Vec<String> {
+        let info = self.info.lock().await;
+        let mut failed_file = info.get_failed_post();
+        failed_file.append(&mut info.get_failed_file());
+        failed_file.append(&mut info.get_interrupted_file());
+        failed_file
+    }
+
+    /// Exit code of the run.
+    pub async fn exit(&self) -> Exit {
+        self.info.lock().await.exit(self.shutdown.is_requested())
+    }
+
+    /// Summary of the run for scripts: parameters, times, per-file results and per-host statistics.
+    pub async fn report(&self) -> JsonValue {
+        let info = self.info.lock().await;
+        let started = info.get_started();
+        let finished = started.map(|_| info.get_finished().unwrap_or_else(Local::now));
+        let time = |t: Option<DateTime<Local>>| t.map(|t| t.to_rfc3339());
+
+        let files = info
+            .get_files()
+            .iter()
+            .map(|file| {
+                object! {
+                    post: file.post.clone(),
+                    url: file.url.clone(),
+                    status: file.status.as_str(),
+                    http_code: file.http_code,
+                    size: file.size,
+                }
+            })
+            .collect::<Vec<_>>();
+        let skipped = info
+            .get_skip_file()
+            .into_iter()
+            .map(|(url, reason)| object! { url: url, reason: reason })
+            .collect::<Vec<_>>();
+        let new_posts = self.new_posts(info.get_new_post());
+        let mut hosts = JsonValue::new_object();
+        for (host, stats) in info.get_hosts() {
+            hosts[host.as_str()] = object! {
+                requests: stats.requests,
+                errors: stats.errors,
+                bytes: stats.bytes,
+            };
+        }
+
+        object! {
+            version: env!("CARGO_PKG_VERSION"),
+            parameters: object! {
+                url: self.link.url(),
+                outdir: self.outdir.clone(),
+                task: self.task_limit,
+                retry: self.retry,
+                adaptive: self.concurrency.is_adaptive(),
+                only: self.filter.only.clone(),
+                exclude: self.filter.exclude.clone(),
+                ext: self.filter.ext.clone(),
+                exclude_ext: self.filter.exclude_ext.clone(),
+                min_size: self.min_size,
+                max_size: self.max_size,
+                segments: self.segments,
+                segment_threshold: self.segment_threshold,
+                api_rps: self.api_limiter.max_rate(),
+                api_task: self.api_task,
+                limit_rate: self.bandwidth.current_rate(),
+                refresh: self.refresh,
+                since: self.since.map(|since| since.to_string()),
+                force: self.force,
+            },
+            started: time(started),
+            finished: time(finished),
+            duration_sec: started
+                .zip(finished)
+                .map(|(start, end)| (end - start).num_milliseconds() as f64 / 1000.0),
+            interrupted: self.shutdown.is_requested(),
+            error: info.get_error(),
+            bytes: info.get_file_size(),
+            success_files: info.get_success_file(),
+            new_posts: new_posts,
+            files: files,
+            skipped_files: skipped,
+            failed_files: info.get_failed_file(),
+            failed_posts: info.get_failed_post(),
+            interrupted_files: info.get_interrupted_file(),
+            hosts: hosts,
+        }
+    }
+
+    /// New posts and failures of the run for `--webhook`, none when the run
+    /// found nothing new and nothing failed.
+    pub async fn notification(&self) -> Option<JsonValue> {
+        let info = self.info.lock().await;
+        let mut failures = info.get_failed_post();
+        failures.append(&mut info.get_failed_file());
+        let new_posts = info.get_new_post();
+        if new_posts.is_empty() && failures.is_empty() && info.get_error().is_none() {
+            return None;
+        }
+        let exit = info.exit(self.shutdown.is_requested());
+        Some(object! {
+            event: if new_posts.is_empty() { "failures" } else { "new_posts" },
+            creator: object! {
+                url: self.link.clear_option().replace("api/v1/", ""),
+                name: self.creator_name.lock().await.clone(),
+            },
+            new_posts: self.new_posts(new_posts),
+            files: object! {
+                success: info.get_success_file(),
+                skipped: info.get_skip_file().len(),
+                failed: info.get_failed_file().len(),
+            },
+            failures: failures,
+            error: info.get_error(),
+            exit_code: exit.code(),
+        })
+    }
+
+    fn new_posts(&self, posts: &[(String, String)]) -> Vec<JsonValue> {
+        posts
+            .iter()
+            .map(|(id, title)| {
+                object! {
+                    id: id.clone(),
+                    title: title.clone(),
+                    url: self.link.post_id(id).replace("api/v1/", ""),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Write a report as pretty printed JSON.
+pub async fn write_report(path: &str, report: &JsonValue) -> anyhow::Result<()> {
+    tokio::fs::write(path, json::stringify_pretty(report.clone(), 1))
+        .await
+        .with_context(|| format!("Cannot write {}", path))
+}
@@ -0,0 +1,459 @@
+use anyhow::Context;
+use futures_util::future::try_join_all;
+use reqwest::{
+    Response, StatusCode,
+    header::{ACCEPT_RANGES, RANGE},
+};
+use std::{fmt, path::Path, sync::Mutex};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+    time::{Duration, sleep},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    declare::{ERROR_REQUEST_DELAY_SEC, TOO_MANY_REQUESTS_DELAY_SEC},
+    logging::{DOWNLOAD, RETRY},
+    request,
+    utils::{SNIFF_BYTES, check_magic, read_head},
+};
+
+use super::{
+    Downloader,
+    estimate::header_size,
+    event::{Event, Wait},
+    info::DownloaderInfo,
+    page_status::StatusBar,
+    space::Reservation,
+};
+
+/// Path of the file which keeps the bytes `start..end` of a file.
+///
+/// The range is part of the name, so a download is resumed with the layout
+/// it started with whatever the current `--segments` is.
+fn part_path(path_to_file: &str, (start, end): (u64, u64)) -> String {
+    format!("{}.part{}-{}", path_to_file, start, end)
+}
+
+/// Byte ranges of the part files left by an interrupted segmented download.
+async fn part_ranges(path_to_file: &str) -> Vec<(u64, u64)> {
+    let path = Path::new(path_to_file);
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str())) else {
+        return Vec::new();
+    };
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.part", name);
+    let mut ranges = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let file_name = entry.file_name();
+        let Some((start, end)) = file_name
+            .to_str()
+            .and_then(|n| n.strip_prefix(&prefix))
+            .and_then(|range| range.split_once('-'))
+        else {
+            continue;
+        };
+        if let (Ok(start), Ok(end)) = (start.parse(), end.parse()) {
+            ranges.push((start, end));
+        }
+    }
+    ranges.sort();
+    ranges
+}
+
+/// The first segment does not match the type of the file.
+#[derive(Debug)]
+struct InvalidContent(String);
+
+impl fmt::Display for InvalidContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid content, {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidContent {}
+
+/// Check the first bytes of a part file against the file type.
+async fn sniff_part(fname: &str, part: &str) -> anyhow::Result<()> {
+    let head = read_head(part).await?;
+    check_magic(fname, &head).map_err(|reason| InvalidContent(reason).into())
+}
+
+/// The ranges cover `0..total_size` exactly once.
+fn covers(ranges: &[(u64, u64)], total_size: u64) -> bool {
+    let mut next = 0;
+    for (start, end) in ranges {
+        if *start != next || start >= end {
+            return false;
+        }
+        next = *end;
+    }
+    next == total_size && !ranges.is_empty()
+}
+
+/// Split `0..total_size` into at most `segments` ranges, the last one may be shorter.
+fn segment_ranges(total_size: u64, segments: usize) -> Vec<(u64, u64)> {
+    let segments = segments.max(1) as u64;
+    let segment_size = total_size.div_ceil(segments);
+    (0..segments)
+        .map(|i| (i * segment_size, total_size.min((i + 1) * segment_size)))
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
+impl Downloader {
+    /// HEAD response of a file whose segmented download was interrupted.
+    ///
+    /// Part files only exist after a segmented download started, so they are
+    /// resumed by segments again instead of the single connection download.
+    /// Without the size of the file they cannot be resumed, they are removed
+    /// and the file is downloaded again over a single connection.
+    pub async fn segment_head(&self, path: &str, path_to_file: &str) -> Option<Response> {
+        let ranges = part_ranges(path_to_file).await;
+        if ranges.is_empty() {
+            return None;
+        }
+
+        let res = self
+            .head(path)
+            .await
+            .filter(|res| header_size(res).is_some());
+        if res.is_none() {
+            warn!(target: DOWNLOAD, file = path_to_file, "no size for segments, download again");
+            for range in ranges {
+                let _ = fs::remove_file(part_path(path_to_file, range)).await;
+            }
+        }
+        res
+    }
+
+    /// Check whether a fresh download should be split into segments.
+    ///
+    /// The file must be above `segment_threshold` and the server must accept
+    /// byte ranges.
+    pub fn accepts_segments(&self, res: &Response, total_size: u64) -> bool {
+        self.segments > 1
+            && res.status() == StatusCode::OK
+            && total_size > self.segment_threshold
+            && res
+                .headers()
+                .get(ACCEPT_RANGES)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.contains("bytes"))
+    }
+
+    /// Download a single byte range, `end` included, into its part file.
+    ///
+    /// The part file is resumed from its current size, so each segment
+    /// continues on its own after a failure or a restart. The first segment
+    /// fails with `InvalidContent` as soon as its first bytes do not match the
+    /// file type.
+    async fn download_segment(
+        &self,
+        path: &str,
+        part: &str,
+        (start, end): (u64, u64),
+        fname: &str,
+        status: &StatusBar,
+        reservation: &Mutex<Reservation>,
+    ) -> anyhow::Result<()> {
+        let mut retry = self.retry;
+        let sniff_len = (SNIFF_BYTES as u64).min(end - start + 1);
+        let mut sniffed = start != 0;
+        'request: loop {
+            let mut downloaded = match fs::metadata(part).await {
+                Ok(meta) => meta.len(),
+                Err(_) => 0,
+            };
+            if !sniffed && downloaded >= sniff_len {
+                sniff_part(fname, part).await?;
+                sniffed = true;
+            }
+            if start + downloaded > end {
+                return Ok(());
+            }
+
+            let permit = self.connections.acquire().await?;
+            let sender = request::new(&self.request)?
+                .get(path)
+                .header(RANGE, format!("bytes={}-{}", start + downloaded, end));
+
+            let res = self.request.send_file(sender).await;
+            self.count_request(path, res.as_ref().ok().map(|res| res.status()))
+                .await;
+            let res = match res {
+                Ok(res) if res.status() == StatusCode::PARTIAL_CONTENT => res,
+                Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    self.concurrency.report_error();
+                    if retry == 0 {
+                        warn!(target: DOWNLOAD, file = fname, part, "too many requests");
+                        return Err(anyhow::anyhow!("Too many requests for segment {}", part));
+                    }
+                    info!(target: RETRY, file = fname, part, "too many requests");
+                    retry -= 1;
+                    // the connection is left to others while waiting.
+                    drop(permit);
+                    sleep(Duration::from_secs(TOO_MANY_REQUESTS_DELAY_SEC)).await;
+                    continue;
+                }
+                res => {
+                    if res.is_ok_and(|res| res.status().is_server_error()) {
+                        self.concurrency.report_error();
+                    }
+                    if retry == 0 {
+                        warn!(target: DOWNLOAD, file = fname, part, "segment failed");
+                        return Err(anyhow::anyhow!("Failed download segment {}", part));
+                    }
+                    info!(target: RETRY, file = fname, part, "retry segment");
+                    retry -= 1;
+                    drop(permit);
+                    sleep(Duration::from_secs(ERROR_REQUEST_DELAY_SEC)).await;
+                    continue;
+                }
+            };
+
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(part)
+                .await?;
+            let mut file = BufWriter::new(file);
+            let mut stream = res.bytes_stream();
+            loop {
+                let item = match self.request.next_chunk(&mut stream).await {
+                    Ok(Some(Ok(item))) => item,
+                    Ok(None) => break,
+                    // failed or stalled, the written bytes are kept and the next range starts after them.
+                    chunk => {
+                        file.flush().await?;
+                        if retry == 0 {
+                            warn!(target: DOWNLOAD, file = fname, part, "segment failed");
+                            return Err(anyhow::anyhow!("Failed download segment {}", part));
+                        }
+                        info!(
+                            target: RETRY,
+                            file = fname,
+                            part,
+                            stalled = chunk.is_err(),
+                            "resume segment"
+                        );
+                        retry -= 1;
+                        if chunk.is_err() {
+                            self.events.emit(Event::FileWaiting {
+                                url: path.to_string(),
+                                name: fname.to_string(),
+                                wait: Wait::Stalled,
+                                status: status.clone(),
+                            });
+                        }
+                        drop(permit);
+                        sleep(Duration::from_secs(1)).await;
+                        continue 'request;
+                    }
+                };
+                if self.is_stopping() {
+                    file.flush().await?;
+                    return Err(anyhow::anyhow!("Interrupted segment {}", part));
+                }
+                self.bandwidth.consume(item.len()).await;
+                file.write_all(&item).await?;
+                reservation.lock().unwrap().written(item.len() as u64);
+                self.concurrency.report_bytes(item.len() as u64);
+                self.events.emit(Event::FileProgress {
+                    url: path.to_string(),
+                    bytes: item.len() as u64,
+                });
+                downloaded += item.len() as u64;
+                if !sniffed && downloaded >= sniff_len {
+                    file.flush().await?;
+                    sniff_part(fname, part).await?;
+                    sniffed = true;
+                }
+            }
+            file.flush().await?;
+            if !sniffed {
+                sniff_part(fname, part).await?;
+            }
+            return Ok(());
+        }
+    }
+
+    /// Downloads a large file over parallel connections and reassembles it.
+    ///
+    /// Returns `false` when one of the segments failed.
+    pub async fn download_segments(
+        &self,
+        path: &str,
+        fname: &str,
+        path_to_file: &str,
+        total_size: u64,
+        status: &StatusBar,
+        download_info: &mut DownloaderInfo,
+    ) -> anyhow::Result<bool> {
+        let mut ranges = part_ranges(path_to_file).await;
+        if !covers(&ranges, total_size) {
+            // parts of another file size, the download starts again.
+            for range in &ranges {
+                let _ = fs::remove_file(part_path(path_to_file, *range)).await;
+            }
+            ranges = segment_ranges(total_size, self.segments);
+        }
+
+        let mut downloaded = 0;
+        for range in &ranges {
+            let part = part_path(path_to_file, *range);
+            if let Ok(meta) = fs::metadata(&part).await {
+                // more bytes than the range, the part is written again.
+                if meta.len() > range.1 - range.0 {
+                    warn!(target: DOWNLOAD, file = fname, part, "oversized segment");
+                    fs::remove_file(&part).await?;
+                    continue;
+                }
+                downloaded += meta.len();
+            }
+        }
+        // stop the run before the disk runs out, the parts are copied into
+        // the final file so the whole size is needed once more.
+        let Some(reservation) = self
+            .ensure_space(total_size.saturating_sub(downloaded) + total_size)
+            .await
+        else {
+            download_info.add_interrupted_file(path.to_string());
+            self.emit_interrupted(status, path, fname);
+            return Ok(false);
+        };
+        let reservation = Mutex::new(reservation);
+
+        debug!(target: DOWNLOAD, file = fname, size = total_size, segments = ranges.len(), "start segments");
+        self.events.emit(Event::FileStarted {
+            url: path.to_string(),
+            name: fname.to_string(),
+            size: total_size,
+            downloaded,
+            attempt: 1,
+            segments: ranges.len(),
+            status: status.clone(),
+        });
+
+        // the other segments are dropped as soon as one fails.
+        let result = try_join_all(ranges.iter().map(|(start, end)| {
+            let part = part_path(path_to_file, (*start, *end));
+            let reservation = &reservation;
+            async move {
+                self.download_segment(path, &part, (*start, end - 1), fname, status, reservation)
+                    .await
+            }
+        }))
+        .await;
+
+        if self.is_stopping() {
+            // part files are kept and resumed by the next run.
+            self.emit_interrupted(status, path, fname);
+            download_info.add_interrupted_file(path.to_string());
+            return Ok(false);
+        }
+        if let Err(err) = result {
+            if let Some(InvalidContent(reason)) = err.downcast_ref::<InvalidContent>() {
+                let first = part_path(path_to_file, ranges[0]);
+                self.reject_content(path, fname, &first, reason).await?;
+                for range in &ranges[1..] {
+                    let _ = fs::remove_file(part_path(path_to_file, *range)).await;
+                }
+                self.emit_failed(status, path, fname, format!("invalid content, {}", reason));
+            } else {
+                self.emit_failed(status, path, fname, "segment failed");
+            }
+            download_info.add_failed_file(path.to_string());
+            return Ok(false);
+        }
+
+        // a server which ignores the end of the range leaves a part too long.
+        for range in &ranges {
+            let part = part_path(path_to_file, *range);
+            if fs::metadata(&part).await.map_or(0, |m| m.len()) != range.1 - range.0 {
+                warn!(target: DOWNLOAD, file = fname, part, "segment of a wrong size");
+                let _ = fs::remove_file(&part).await;
+                self.emit_failed(status, path, fname, "segment of a wrong size");
+                download_info.add_failed_file(path.to_string());
+                return Ok(false);
+            }
+        }
+
+        // reassemble the parts into the final file.
+        let mut file = fs::File::create(path_to_file)
+            .await
+            .context("cannot create file")?;
+        for range in &ranges {
+            let part = part_path(path_to_file, *range);
+            let mut part_file = fs::File::open(&part).await?;
+            tokio::io::copy(&mut part_file, &mut file).await?;
+        }
+        file.flush().await?;
+        for range in &ranges {
+            let _ = fs::remove_file(part_path(path_to_file, *range)).await;
+        }
+
+        download_info.add_file_size(total_size);
+        download_info.add_host_bytes(path, total_size);
+        download_info.add_success_file(1);
+        self.emit_finished(status, path, fname, Some(total_size));
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_cover_the_file() {
+        assert_eq!(
+            segment_ranges(100, 4),
+            [(0, 25), (25, 50), (50, 75), (75, 100)]
+        );
+        // the last segment keeps what is left.
+        assert_eq!(segment_ranges(10, 3), [(0, 4), (4, 8), (8, 10)]);
+        // no empty segment when there are more segments than bytes.
+        assert_eq!(segment_ranges(2, 4), [(0, 1), (1, 2)]);
+        assert_eq!(segment_ranges(5, 0), [(0, 5)]);
+        for (total_size, segments) in [(100, 4), (10, 3), (2, 4), (104_857_601, 8)] {
+            assert!(covers(&segment_ranges(total_size, segments), total_size));
+        }
+    }
+
+    #[test]
+    fn covers_rejects_other_layouts() {
+        assert!(covers(&[(0, 4), (4, 8), (8, 10)], 10));
+        assert!(!covers(&[], 0));
+        assert!(!covers(&[(0, 4), (8, 10)], 10), "gap");
+        assert!(!covers(&[(0, 5), (4, 10)], 10), "overlap");
+        assert!(!covers(&[(0, 4), (4, 8)], 10), "last segment missing");
+        assert!(!covers(&[(0, 4), (4, 8), (8, 12)], 10), "past the end");
+        assert!(!covers(&[(0, 4), (4, 4), (4, 10)], 10), "empty range");
+    }
+
+    #[tokio::test]
+    async fn part_ranges_of_a_file() {
+        let dir = std::env::temp_dir().join(format!("cktool-parts-{}", std::process::id()));
+        fs::create_dir_all(&dir).await.unwrap();
+        let file = dir.join("a.mp4");
+        let file = file.to_str().unwrap();
+        for name in [
+            "a.mp4.part8-10",
+            "a.mp4.part0-4",
+            "a.mp4.part4-8",
+            "a.mp4",
+            "a.mp4.partx-4",
+            "b.mp4.part0-4",
+        ] {
+            fs::write(dir.join(name), b"").await.unwrap();
+        }
+        let ranges = part_ranges(file).await;
+        fs::remove_dir_all(&dir).await.unwrap();
+        assert_eq!(ranges, [(0, 4), (4, 8), (8, 10)]);
+        assert!(part_ranges(file).await.is_empty());
+    }
+}
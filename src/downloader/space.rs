@@ -0,0 +1,60 @@
+use std::sync::{Arc, Mutex};
+
+/// Bytes still to be written by the files in progress.
+///
+/// The free space of the disk only shrinks as bytes are written, a file is
+/// checked against what is left after the other downloads are done.
+#[derive(Default)]
+pub struct DiskSpace {
+    reserved: Mutex<u64>,
+}
+
+/// Bytes kept for one file until dropped, released as they are written.
+pub struct Reservation {
+    space: Arc<DiskSpace>,
+    bytes: u64,
+}
+
+impl DiskSpace {
+    pub fn reserved(&self) -> u64 {
+        *self.reserved.lock().unwrap()
+    }
+
+    /// Reserve `needed` bytes when they fit in `available` with the other reservations.
+    pub fn reserve(self: &Arc<Self>, needed: u64, available: u64) -> Result<Reservation, u64> {
+        let mut reserved = self.reserved.lock().unwrap();
+        let total = reserved.saturating_add(needed);
+        if total > available {
+            return Err(total);
+        }
+        *reserved = total;
+        Ok(Reservation {
+            space: self.clone(),
+            bytes: needed,
+        })
+    }
+
+    /// Reserve without a check, used by `--force` and when the free space is unknown.
+    pub fn reserve_unchecked(self: &Arc<Self>, needed: u64) -> Reservation {
+        *self.reserved.lock().unwrap() += needed;
+        Reservation {
+            space: self.clone(),
+            bytes: needed,
+        }
+    }
+}
+
+impl Reservation {
+    /// `bytes` were written to the disk, they count in its free space now.
+    pub fn written(&mut self, bytes: u64) {
+        let bytes = bytes.min(self.bytes);
+        self.bytes -= bytes;
+        *self.space.reserved.lock().unwrap() -= bytes;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        *self.space.reserved.lock().unwrap() -= self.bytes;
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{Context, anyhow};
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::request::{self, RequestOptions};
+
+/// Kind of favorites of an account.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FavoriteType {
+    /// favorited creators.
+    Artist,
+    /// favorited posts.
+    Post,
+}
+
+impl FavoriteType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FavoriteType::Artist => "artist",
+            FavoriteType::Post => "post",
+        }
+    }
+}
+
+/// A favorited creator or post.
+#[derive(Clone, Debug)]
+pub struct Favorite {
+    pub service: String,
+    /// id of the creator.
+    pub creator: String,
+    /// id of the post, `None` for a favorited creator.
+    pub post: Option<String>,
+    /// name of the creator or title of the post.
+    pub name: String,
+}
+
+impl Favorite {
+    /// Web url of the creator or post, as given on the command line.
+    pub fn url(&self, site: &str) -> String {
+        let creator = format!("{}/{}/user/{}", site, self.service, self.creator);
+        match &self.post {
+            Some(post) => format!("{}/post/{}", creator, post),
+            None => creator,
+        }
+    }
+
+    /// Output folder inside the favorites folder.
+    ///
+    /// A favorited post gets a folder of its own inside the one of its creator,
+    /// each folder keeps the manifest of a single run.
+    pub fn folder(&self) -> String {
+        let creator = format!("{}_{}", self.service, self.creator);
+        match &self.post {
+            Some(post) => format!("{}/{}", creator, post),
+            None => creator,
+        }
+    }
+}
+
+/// Origin of the site, e.g. `https://kemono.cr` for any url of the site.
+pub fn site(url: &str) -> anyhow::Result<String> {
+    let url = Url::parse(url).context("Url is invalid")?;
+    Ok(url.origin().unicode_serialization())
+}
+
+/// Fetch the favorites of the account logged in with the session cookie.
+pub async fn fetch(
+    site: &str,
+    typ: FavoriteType,
+    options: &RequestOptions,
+) -> anyhow::Result<Vec<Favorite>> {
+    let url = format!("{}/api/v1/account/favorites?type={}", site, typ.as_str());
+    let res = options
+        .send(request::new(options)?.get(&url))
+        .await
+        .context("Failed fetch favorites")?;
+    match res.status() {
+        StatusCode::OK => {}
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            return Err(anyhow!(
+                "Not logged in, set the session cookie with --session or --cookies"
+            ));
+        }
+        status => return Err(anyhow!("Failed fetch favorites, status: {}", status)),
+    }
+    let content = res.text().await.context("Failed fetch favorites")?;
+    let obj = json::parse(&content).context("Invalid favorites response")?;
+
+    let favorites = obj
+        .members()
+        .filter_map(|item| {
+            let service = item["service"].as_str()?.to_string();
+            let id = item["id"].to_string();
+            Some(match typ {
+                FavoriteType::Artist => Favorite {
+                    service,
+                    creator: id,
+                    post: None,
+                    name: item["name"].as_str().unwrap_or_default().to_string(),
+                },
+                FavoriteType::Post => Favorite {
+                    service,
+                    creator: item["user"].as_str()?.to_string(),
+                    post: Some(id),
+                    name: item["title"].as_str().unwrap_or_default().to_string(),
+                },
+            })
+        })
+        .collect();
+    Ok(favorites)
+}
@@ -1,5 +1,12 @@
+pub mod category;
+pub mod config;
 pub mod declare;
 pub mod downloader;
+pub mod favorites;
 pub mod link;
+pub mod logging;
 pub mod request;
+pub mod subscriptions;
 pub mod utils;
+pub mod watch;
+pub mod webhook;
@@ -1,4 +1,6 @@
 use anyhow::{Ok, Result};
+
+use crate::declare::PAGE_SIZE;
 use url::Url;
 
 /// specific page download.
@@ -47,7 +49,7 @@ impl Link {
         }
         match self.page {
             Page::All => self.url.clone(),
-            Page::One(page_number) => format!("{}/posts?o={}", self.url, page_number * 50),
+            Page::One(page_number) => format!("{}/posts?o={}", self.url, page_number * PAGE_SIZE),
         }
     }
     /// create instance of Link.
@@ -89,6 +91,12 @@ impl Link {
             .expect("Cannot clear option from url")
             .to_string()
     }
+    /// produces Url of the creator profile which contains `post_count`.
+    ///
+    /// example returned value `https://example.com/api/v1/service/user/id/profile`
+    pub fn profile(&self) -> String {
+        format!("{}/profile", self.clear_option())
+    }
     pub fn page_increst(&mut self) {
         if let Page::One(page_number) = self.page {
             self.page = Page::One(page_number + 1);
@@ -125,3 +133,14 @@ impl Link {
         None
     }
 }
+
+/// Service and id of the creator of a url, e.g. `https://kemono.cr/fanbox/user/12345`.
+pub fn creator_id(url: &str) -> Option<(String, String)> {
+    let url = Url::parse(url).ok()?;
+    let segments = url.path_segments()?.collect::<Vec<_>>();
+    let user = segments.iter().position(|s| *s == "user")?;
+    Some((
+        segments.get(user.checked_sub(1)?)?.to_string(),
+        segments.get(user + 1)?.to_string(),
+    ))
+}
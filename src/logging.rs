@@ -0,0 +1,125 @@
+use anyhow::{Context, anyhow};
+use std::path::Path;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{EnvFilter, fmt::MakeWriter};
+
+/// Target of API requests: pages, profiles and posts.
+pub const API: &str = "api";
+/// Target of file downloads and their result.
+pub const DOWNLOAD: &str = "download";
+/// Target of retries, waits and reconnections.
+pub const RETRY: &str = "retry";
+/// Target of the polls of `cktool watch`.
+pub const WATCH: &str = "watch";
+
+/// Default filter of `--log-level`.
+pub const DEFAULT_LEVEL: &str = "info";
+/// Filter of `--verbose`, debug events of the libraries are left out.
+pub const VERBOSE_LEVEL: &str = "info,api=debug,download=debug,retry=debug,watch=debug";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("invalid log format `{}`, use text or json", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogRotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl LogRotation {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "never" => Ok(LogRotation::Never),
+            "hourly" => Ok(LogRotation::Hourly),
+            "daily" => Ok(LogRotation::Daily),
+            _ => Err(format!(
+                "invalid log rotation `{}`, use never, hourly or daily",
+                value
+            )),
+        }
+    }
+
+    fn rotation(self) -> Rotation {
+        match self {
+            LogRotation::Never => Rotation::NEVER,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+        }
+    }
+}
+
+/// Where and how log events are written.
+#[derive(Clone, Debug, Default)]
+pub struct LogOptions {
+    /// log file, the terminal when none.
+    pub file: Option<String>,
+    /// filter directives, e.g. `info` or `warn,api=debug,retry=info`.
+    pub level: Option<String>,
+    pub format: LogFormat,
+    /// a rotated file gets the date as suffix, e.g. `cktool.log.2024-01-31`.
+    pub rotation: LogRotation,
+}
+
+impl LogOptions {
+    /// Install the global subscriber, `terminal` writes the lines when no file is set.
+    ///
+    /// A caller drawing progress bars gives a writer which hides them, so
+    /// both never interleave.
+    pub fn init<W>(&self, terminal: W) -> anyhow::Result<()>
+    where
+        W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+    {
+        let filter = EnvFilter::try_new(self.level.as_deref().unwrap_or(DEFAULT_LEVEL))
+            .context("Invalid log level")?;
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_target(true);
+        let result = match &self.file {
+            Some(file) => {
+                let path = Path::new(file);
+                let dir = path
+                    .parent()
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .unwrap_or(Path::new("."));
+                let name = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .with_context(|| format!("Invalid log file {}", file))?;
+                let appender = RollingFileAppender::builder()
+                    .rotation(self.rotation.rotation())
+                    .filename_prefix(name)
+                    .build(dir)
+                    .with_context(|| format!("Cannot open log file {}", file))?;
+                let builder = builder.with_writer(appender).with_ansi(false);
+                match self.format {
+                    LogFormat::Text => builder.try_init(),
+                    LogFormat::Json => builder.json().try_init(),
+                }
+            }
+            None => {
+                let builder = builder.with_writer(terminal);
+                match self.format {
+                    LogFormat::Text => builder.try_init(),
+                    LogFormat::Json => builder.json().try_init(),
+                }
+            }
+        };
+        result.map_err(|err| anyhow!("Cannot initialize logging: {}", err))
+    }
+}
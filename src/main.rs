@@ -1,23 +1,47 @@
 // Import required dependencies for CLI argument parsing and shell completion
+use chrono::NaiveDate;
 use cktool::{
-    declare::{RetryType, TASK, TaskType},
-    downloader::Downloader,
+    category::{Categories, FileFilter},
+    config::Config,
+    declare::{
+        API_RPS, API_TASK, CONNECT_TIMEOUT_SEC, Exit, REQUEST_TIMEOUT_SEC, RetryType,
+        SEGMENT_THRESHOLD, SEGMENTS, STALL_TIMEOUT_SEC, SYNC_CREATORS, TASK, TaskType,
+        WATCH_INTERVAL_SEC, WATCH_JITTER_SEC, WATCH_MAX_BACKOFF_SEC,
+    },
+    downloader::{Downloader, Manifest, write_report},
+    favorites::{self, FavoriteType},
     link::{Link, Page},
-    utils::Log,
+    logging::{LogFormat, LogOptions, LogRotation, VERBOSE_LEVEL, WATCH},
+    request::RequestOptions,
+    subscriptions::{self, Subscription},
+    utils::{Bandwidth, Log, RateSchedule, Shutdown, parse_size},
+    watch::Schedule,
+    webhook::{Webhook, WebhookFormat},
+};
+use clap::{
+    ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+    builder::RangedU64ValueParser,
 };
-use clap::{CommandFactory, Parser};
 use clap_complete::{Shell, generate};
-use std::io;
+use colored::Colorize;
+use futures_util::{StreamExt, future::join_all, stream};
+use json::JsonValue;
+use std::{ffi::OsString, io, process::ExitCode, sync::Arc, time::Duration};
+use tokio::{sync::Semaphore, time::sleep};
+use toml::Table;
+use tracing::{debug, info, warn};
+
+mod cli;
 
 /// Command line arguments structure for the cktool
 #[derive(Parser)]
 #[command(name = "cktool", version, about)]
 struct Args {
     /// Output directory for downloaded content
-    #[arg(short, long, value_name = "Folder")]
+    #[arg(global = true, short, long, value_name = "Folder")]
     out: Option<String>,
     /// Number of concurrent download tasks
-    #[arg(short, long, default_value_t = TASK)]
+    #[arg(global = true, short, long, default_value_t = TASK)]
     task: TaskType,
     /// URL of the profile account or post to download content from
     #[arg(value_name = "url")]
@@ -29,41 +53,323 @@ struct Args {
     #[arg(short,long, default_value=None, value_name="Number")]
     page: Option<u64>,
     /// specify the maximum number of re-download when failed.
-    #[arg(short, long, default_value=None)]
+    #[arg(global = true, short, long, default_value=None)]
     retry: Option<RetryType>,
     /// Download only video files
-    #[arg(short = 'v', long, default_value_t = false)]
+    #[arg(global = true, short = 'v', long, default_value_t = false)]
     video_only: bool,
     /// Download only image files
-    #[arg(short = 'i', long, default_value_t = false)]
+    #[arg(global = true, short = 'i', long, default_value_t = false)]
     image_only: bool,
-    /// enable verbose logging
-    #[arg(long, default_value_t = false)]
+    /// Download only these categories, e.g. image,video (image, video, audio, archive, document, project, other)
+    #[arg(global = true, long, value_delimiter = ',', value_name = "Category")]
+    only: Vec<String>,
+    /// Skip these categories, e.g. archive,project
+    #[arg(global = true, long, value_delimiter = ',', value_name = "Category")]
+    exclude: Vec<String>,
+    /// Download only these extensions, besides the categories of --only
+    #[arg(global = true, long, value_delimiter = ',', value_name = "Ext")]
+    ext: Vec<String>,
+    /// Skip these extensions
+    #[arg(global = true, long, value_delimiter = ',', value_name = "Ext")]
+    exclude_ext: Vec<String>,
+    /// Skip files smaller than this size (e.g. 5K)
+    #[arg(global = true, long, value_parser = parse_size, value_name = "Size")]
+    min_size: Option<u64>,
+    /// Skip files larger than this size (e.g. 4G)
+    #[arg(global = true, long, value_parser = parse_size, value_name = "Size")]
+    max_size: Option<u64>,
+    /// enable verbose logging, events down to the debug level are printed
+    #[arg(global = true, long, default_value_t = false)]
     verbose: bool,
+    /// Write log events to this file instead of the terminal
+    #[arg(global = true, long, value_name = "File")]
+    log_file: Option<String>,
+    /// Log filter, e.g. info or warn,api=debug,retry=info (targets: api, download, retry)
+    #[arg(global = true, long, value_name = "Filter")]
+    log_level: Option<String>,
+    /// Format of log events: text or json
+    #[arg(global = true, long, default_value = "text", value_parser = LogFormat::parse, value_name = "Format")]
+    log_format: LogFormat,
+    /// Start a new log file every hour or day: never, hourly or daily
+    #[arg(global = true, long, default_value = "never", value_parser = LogRotation::parse, value_name = "Rotation")]
+    log_rotate: LogRotation,
     /// save failed posts to file
-    #[arg(long,short, value_name="File", default_value = None)]
+    #[arg(global = true, long,short, value_name="File", default_value = None)]
     log: Option<Option<String>>,
+    /// Write a JSON summary of the run to this file
+    #[arg(global = true, long, value_name = "File")]
+    report: Option<String>,
+    /// POST the new posts and the failures of each run to this url
+    #[arg(global = true, long, value_name = "Url")]
+    webhook: Option<String>,
+    /// Body of the webhook: json, discord or slack
+    #[arg(global = true, long, default_value = "json", value_parser = WebhookFormat::parse, value_name = "Format")]
+    webhook_format: WebhookFormat,
+    /// Number of parallel connections for a single large file
+    #[arg(global = true, long, default_value_t = SEGMENTS, value_parser = RangedU64ValueParser::<usize>::new().range(1..), value_name = "Number")]
+    segments: usize,
+    /// Files larger than this size are downloaded in segments (e.g. 100M, 1G)
+    #[arg(global = true, long, default_value_t = SEGMENT_THRESHOLD, value_parser = parse_size, value_name = "Size")]
+    segment_size: u64,
+    /// Maximum number of download connections at once [default: task + segments]
+    #[arg(global = true, long, value_name = "Number")]
+    max_connections: Option<usize>,
+    /// Limit the download speed of all tasks together (e.g. 500K, 5M)
+    #[arg(global = true, long, value_parser = parse_size, value_name = "Rate")]
+    limit_rate: Option<u64>,
+    /// Change the rate limit by time of day (e.g. 01:00-07:00=unlimited,18:00-23:00=1M)
+    #[arg(global = true, long, value_parser = RateSchedule::parse, value_name = "Schedule")]
+    limit_schedule: Option<RateSchedule>,
+    /// Maximum number of API requests per second, lowered automatically on 429 errors
+    #[arg(global = true, long, default_value_t = API_RPS, value_name = "Number")]
+    api_rps: f64,
+    /// Number of API pages fetched at the same time
+    #[arg(global = true, long, default_value_t = API_TASK, value_parser = RangedU64ValueParser::<usize>::new().range(1..), value_name = "Number")]
+    api_task: usize,
+    /// Scale the number of tasks on server feedback, `--task` is the maximum
+    #[arg(global = true, long, default_value_t = false)]
+    adaptive: bool,
+    /// Continue the job saved in the output folder, the url can be omitted
+    #[arg(long, default_value_t = false)]
+    resume: bool,
+    /// Only download posts published on or after this day (e.g. 2024-01-31)
+    #[arg(global = true, long, value_parser = parse_date, value_name = "Date")]
+    since: Option<NaiveDate>,
+    /// Fetch pages and unfinished posts again instead of using the saved job
+    #[arg(global = true, long, default_value_t = false)]
+    refresh: bool,
+    /// Print the size of all files and the free space, then exit without downloading
+    #[arg(long, default_value_t = false)]
+    estimate: bool,
+    /// Resolve pages and posts and list the files which would be downloaded, nothing is written
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+    /// Fetch the size and type of each file with a HEAD request in --dry-run
+    #[arg(long, default_value_t = false)]
+    head: bool,
+    /// Write the --dry-run file list to this file, JSON when it ends with .json, CSV otherwise
+    #[arg(long, value_name = "File")]
+    export: Option<String>,
+    /// Download even when the files would not fit on the disk, sizes are not fetched before the download starts
+    #[arg(global = true, long, default_value_t = false)]
+    force: bool,
+    /// Proxy for all requests, e.g. http://host:port or socks5h://host:port
+    #[arg(global = true, long, value_name = "Url")]
+    proxy: Option<String>,
+    /// Proxy for API requests only
+    #[arg(global = true, long, value_name = "Url")]
+    api_proxy: Option<String>,
+    /// Proxy for media files on data nodes only
+    #[arg(global = true, long, value_name = "Url")]
+    data_proxy: Option<String>,
+    /// Extra header sent with every request, e.g. "Referer: https://site/" (repeatable)
+    #[arg(global = true, long = "header", short = 'H', value_name = "Header")]
+    headers: Vec<String>,
+    /// User-Agent sent with every request
+    #[arg(global = true, long, value_name = "Text")]
+    user_agent: Option<String>,
+    /// Load cookies from a Netscape cookies.txt file
+    #[arg(global = true, long, value_name = "File")]
+    cookies: Option<String>,
+    /// Value of the `session` cookie of a logged in account
+    #[arg(global = true, long, value_name = "Value")]
+    session: Option<String>,
+    /// Seconds to wait for a connection, 0 disables the timeout
+    #[arg(global = true, long, default_value_t = CONNECT_TIMEOUT_SEC, value_name = "Seconds")]
+    connect_timeout: u64,
+    /// Seconds to wait for a response, 0 disables the timeout
+    #[arg(global = true, long, default_value_t = REQUEST_TIMEOUT_SEC, value_name = "Seconds")]
+    timeout: u64,
+    /// Retry a download when no bytes arrived for this many seconds, 0 disables the timeout
+    #[arg(global = true, long, default_value_t = STALL_TIMEOUT_SEC, value_name = "Seconds")]
+    stall_timeout: u64,
+    /// Config file [default: $XDG_CONFIG_HOME/cktool/config.toml]
+    #[arg(global = true, long, value_name = "File")]
+    config: Option<String>,
+    /// Use the options of this profile of the config file
+    #[arg(global = true, long, value_name = "Name")]
+    profile: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Download the favorites of the account logged in with --session or --cookies
+    Favorites {
+        /// Url of the site, e.g. https://kemono.cr
+        #[arg(value_name = "url")]
+        site: String,
+        /// Download favorited creators or favorited posts
+        #[arg(long = "type", value_enum, default_value_t = FavoriteKind::Artist)]
+        kind: FavoriteKind,
+        /// Only list the favorites
+        #[arg(long, default_value_t = false)]
+        list: bool,
+    },
+    /// Download the new posts of every creator of the subscriptions file
+    Sync {
+        /// Subscriptions file [default: $XDG_CONFIG_HOME/cktool/subscriptions.toml]
+        #[arg(long, value_name = "File")]
+        file: Option<String>,
+        /// Number of creators downloaded at the same time, they share --task and the rate limits
+        #[arg(long, default_value_t = SYNC_CREATORS, value_name = "Number")]
+        creators: usize,
+    },
+    /// Keep running and download new posts of the subscriptions file as they appear
+    Watch {
+        /// Subscriptions file [default: $XDG_CONFIG_HOME/cktool/subscriptions.toml]
+        #[arg(long, value_name = "File")]
+        file: Option<String>,
+        /// Number of creators polled at the same time, they share --task and the rate limits
+        #[arg(long, default_value_t = SYNC_CREATORS, value_name = "Number")]
+        creators: usize,
+        /// Seconds between two polls of a creator
+        #[arg(long, default_value_t = WATCH_INTERVAL_SEC, value_name = "Seconds")]
+        interval: u64,
+        /// Random seconds up to this value added to each poll
+        #[arg(long, default_value_t = WATCH_JITTER_SEC, value_name = "Seconds")]
+        jitter: u64,
+        /// Longest seconds between two polls of a creator which keeps failing
+        #[arg(long, default_value_t = WATCH_MAX_BACKOFF_SEC, value_name = "Seconds")]
+        max_backoff: u64,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FavoriteKind {
+    Artist,
+    Post,
+}
+
+impl From<FavoriteKind> for FavoriteType {
+    fn from(kind: FavoriteKind) -> Self {
+        match kind {
+            FavoriteKind::Artist => FavoriteType::Artist,
+            FavoriteKind::Post => FavoriteType::Post,
+        }
+    }
 }
 
 #[tokio::main]
-async fn main() {
-    let args = Args::parse();
+async fn main() -> ExitCode {
+    let argv = std::env::args_os().collect::<Vec<_>>();
+    let matches = Args::command().get_matches_from(&argv);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
 
     // Handle shell completion generation if requested
     if let Some(shell) = &args.completion {
         let mut args_cli = Args::command();
         generate(*shell, &mut args_cli, "cktool", &mut io::stdout());
-        return;
+        return Exit::Success.into();
     }
 
-    if let Some(url) = args.url {
-        if args.video_only && args.image_only {
-            eprintln!("Error: Cannot use --video-only and --image-only together.");
-            return;
+    let command_line = match Config::load(args.config.as_deref()) {
+        Ok(config) => CommandLine {
+            argv,
+            matches,
+            config,
+        },
+        Err(err) => {
+            eprintln!("{:#}", err);
+            return Exit::InvalidInput.into();
         }
+    };
+    let site_url = match &args.command {
+        Some(Command::Favorites { site, .. }) => Some(site.as_str()),
+        Some(Command::Sync { .. } | Command::Watch { .. }) => None,
+        None => args.url.as_deref(),
+    };
+    let (mut args, mut config) = match resolve_args(&command_line, site_url, None) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            return Exit::InvalidInput.into();
+        }
+    };
+
+    if args.video_only && args.image_only {
+        eprintln!("Error: Cannot use --video-only and --image-only together.");
+        return Exit::InvalidInput.into();
+    }
+
+    if let Err(err) = init_logging(&args) {
+        eprintln!("{:#}", err);
+        return Exit::InvalidInput.into();
+    }
+
+    match args.command.take() {
+        Some(Command::Favorites { site, kind, list }) => {
+            return download_favorites(&args, &command_line, &site, kind.into(), list)
+                .await
+                .into();
+        }
+        Some(Command::Sync { file, creators }) => {
+            return sync(&args, &command_line, &config, file.as_deref(), creators)
+                .await
+                .into();
+        }
+        Some(Command::Watch {
+            file,
+            creators,
+            interval,
+            jitter,
+            max_backoff,
+        }) => {
+            let schedule = Schedule {
+                interval: Duration::from_secs(interval.max(1)),
+                jitter: Duration::from_secs(jitter),
+                max_backoff: Duration::from_secs(max_backoff),
+            };
+            return watch(
+                &args,
+                &command_line,
+                &config,
+                file.as_deref(),
+                creators,
+                schedule,
+            )
+            .await
+            .into();
+        }
+        None => {}
+    }
+
+    // continue the job of the output folder when no url is given.
+    let mut resume_page = None;
+    let url = match args.url.take() {
+        Some(url) => Some(url),
+        None if args.resume => {
+            let out = args.out.clone().unwrap_or(".".to_string());
+            match Manifest::load(&out).await {
+                Some(manifest) => {
+                    let url = manifest.web_url();
+                    // options of the site and creator of the saved job.
+                    (args, config) = match resolve_args(&command_line, Some(&url), None) {
+                        Ok(v) => v,
+                        Err(err) => {
+                            eprintln!("{:#}", err);
+                            return Exit::InvalidInput.into();
+                        }
+                    };
+                    resume_page = manifest.page;
+                    args.out = Some(out);
+                    Some(url)
+                }
+                None => {
+                    eprintln!("No saved job found in {} folder.", out);
+                    return Exit::InvalidInput.into();
+                }
+            }
+        }
+        None => None,
+    };
+
+    if let Some(url) = url {
         // Process download request if URL is provided
         // Determine output directory - use URL's last segment if not specified
-        let out_dir = match args.out {
+        let out_dir = match args.out.clone() {
             Some(path) => path,
             None => {
                 let url = url.split("/").last().unwrap().to_string();
@@ -78,46 +384,643 @@ async fn main() {
             if let Some(page) = args.page {
                 // first page is zero.
                 link.page = Page::One(page - 1);
+            } else if let Some(page) = resume_page {
+                link.page = Page::One(page);
             } else {
                 link.page = Page::All
             }
-            let retry = match args.retry {
-                Some(re) => re,
-                None => (args.task as RetryType) * 10,
-            };
+            // the first Ctrl+C stops new work, the report and failed log are still written.
+            let shutdown = Arc::new(Shutdown::new());
+            shutdown.listen(cli::on_signal);
             // Start the download process with specified parameters
-            let mut downloader = Downloader::new(
-                link,
-                args.task,
-                out_dir.clone(),
-                retry,
-                args.video_only,
-                args.image_only,
-                args.verbose,
-            );
-            match downloader.all().await {
-                Ok(_) => {
-                    downloader.print_reports().await;
-                    // if log flag is exist.
-                    if let Some(log) = args.log {
-                        let log_file = if let Some(log) = log {
-                            log
-                        } else {
-                            // default log's file name.
-                            "failed.log".to_string()
-                        };
-                        let failed_files = downloader.failed_file().await;
-                        if !failed_files.is_empty() {
-                            Log::save_failed(&failed_files, &log_file).await;
+            let mut downloader = match create_downloader(&args, &config, link, out_dir, shutdown) {
+                Ok(downloader) => downloader,
+                Err(err) => {
+                    eprintln!("{:#}", err);
+                    return Exit::InvalidInput.into();
+                }
+            };
+            if args.dry_run {
+                cli::print_parameters(&downloader);
+                return match downloader.dry_run(args.head).await {
+                    Ok(dry_run) => {
+                        cli::print_dry_run(&dry_run);
+                        if let Some(path) = &args.export
+                            && let Err(err) = dry_run.export(path).await
+                        {
+                            eprintln!("{:#}", err);
+                            return Exit::Failure.into();
                         }
+                        Exit::Success.into()
                     }
-                }
-                Err(err) => eprintln!("{}", err),
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Exit::Failure.into()
+                    }
+                };
             }
+            if args.estimate {
+                cli::print_parameters(&downloader);
+                return match downloader.estimate().await {
+                    Ok(estimate) => {
+                        cli::print_estimate(&estimate);
+                        Exit::Success.into()
+                    }
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Exit::Failure.into()
+                    }
+                };
+            }
+            let failed_files = run(&mut downloader).await;
+            notify(&args, &downloader).await;
+            save_log(&args, &failed_files).await;
+            if let Some(path) = &args.report {
+                save_report(path, &downloader.report().await).await;
+            }
+            downloader.exit().await.into()
         } else {
             eprintln!("Url is invalid");
+            Exit::InvalidInput.into()
         }
     } else {
         let _ = Args::command().print_help();
+        Exit::InvalidInput.into()
+    }
+}
+
+/// The command line, parsed once, and the config file it names.
+struct CommandLine {
+    argv: Vec<OsString>,
+    matches: ArgMatches,
+    config: Config,
+}
+
+/// Arguments of the command line completed with the config file.
+///
+/// Options which are not given are taken from the top-level keys of the config,
+/// then from the profile, the site and the creator of `url`, and at last from
+/// `entry` of the subscriptions file. Returns the settings of the config which
+/// apply to this url too.
+fn resolve_args(
+    command_line: &CommandLine,
+    url: Option<&str>,
+    entry: Option<&Table>,
+) -> anyhow::Result<(Args, Config)> {
+    let CommandLine {
+        argv,
+        matches,
+        config,
+    } = command_line;
+    let profile = matches
+        .get_one::<String>("profile")
+        .cloned()
+        .or_else(|| config.get_str("profile"));
+    let mut config = config.resolve(profile.as_deref(), url)?;
+    if let Some(entry) = entry {
+        config = config.with_section(entry);
+    }
+    let source = match entry {
+        Some(_) => format!("{} or subscriptions file", config.source()),
+        None => config.source(),
+    };
+    let mut argv = argv.clone();
+    let command = Args::command();
+    let defaults = config
+        .to_args(&command, matches)
+        .map_err(|err| match entry {
+            Some(_) => anyhow::anyhow!("{} or subscriptions file", err),
+            None => err,
+        })?;
+    argv.splice(1..1, defaults.into_iter().map(OsString::from));
+    // options of `sync` or `watch`, the subcommand is the last one on the command line.
+    argv.extend(
+        config
+            .subcommand_args(&command, matches)
+            .into_iter()
+            .map(OsString::from),
+    );
+    // the command line was parsed above, an error comes from the config.
+    let args = Args::try_parse_from(argv).map_err(|err| {
+        let message = err.to_string();
+        let message = message.lines().next().unwrap_or_default();
+        anyhow::anyhow!(
+            "Invalid option in the {}: {}",
+            source,
+            message.trim_start_matches("error: ")
+        )
+    })?;
+    Ok((args, config))
+}
+
+/// Create a downloader with the options of the command line.
+fn create_downloader(
+    args: &Args,
+    config: &Config,
+    link: Link,
+    out_dir: String,
+    shutdown: Arc<Shutdown>,
+) -> anyhow::Result<Downloader> {
+    let mut builder = Downloader::builder(link)
+        .task(args.task)
+        .outdir(out_dir)
+        .video_only(args.video_only)
+        .image_only(args.image_only)
+        .verbose(args.verbose)
+        .segments(args.segments, args.segment_size)
+        .bandwidth(Bandwidth::new(
+            args.limit_rate,
+            args.limit_schedule.clone().unwrap_or_default(),
+        ))
+        .api_rps(args.api_rps)
+        .api_task(args.api_task)
+        .adaptive(args.adaptive)
+        .refresh(args.refresh)
+        .force(args.force)
+        .size_range(args.min_size, args.max_size)
+        .since(args.since)
+        .filter(file_filter(args, config)?)
+        .request(request_options(args)?)
+        .shutdown(shutdown);
+    if let Some(retry) = args.retry {
+        builder = builder.retry(retry);
+    }
+    if let Some(connections) = args.max_connections {
+        builder = builder.max_connections(connections);
+    }
+    let progress = cli::Progress::default();
+    Ok(builder
+        .on_event(move |event| progress.handle(event))
+        .build())
+}
+
+/// Download everything and print the report, returns the failed files.
+async fn run(downloader: &mut Downloader) -> Vec<String> {
+    cli::print_parameters(downloader);
+    // the summary of a failed run tells what was done before the error.
+    if let Err(err) = downloader.all().await {
+        eprintln!("{}", err);
+    }
+    cli::print_reports(downloader).await;
+    downloader.failed_file().await
+}
+
+/// Write the JSON report, an error is only printed.
+async fn save_report(path: &str, report: &JsonValue) {
+    if let Err(err) = write_report(path, report).await {
+        eprintln!("{:#}", err);
+    }
+}
+
+/// Save failed files when the log flag is given.
+async fn save_log(args: &Args, failed_files: &[String]) {
+    if let Some(log) = &args.log {
+        let log_file = if let Some(log) = log {
+            log.clone()
+        } else {
+            // default log's file name.
+            "failed.log".to_string()
+        };
+        if !failed_files.is_empty() {
+            Log::save_failed(failed_files, &log_file).await;
+        }
+    }
+}
+
+/// Download every favorited creator or post, each creator in its own folder.
+///
+/// Pages of favorited creators are fetched again on each run, posts already
+/// downloaded are skipped through the manifest of the creator folder.
+async fn download_favorites(
+    args: &Args,
+    command_line: &CommandLine,
+    url: &str,
+    typ: FavoriteType,
+    list: bool,
+) -> Exit {
+    let site = match favorites::site(url) {
+        Ok(site) => site,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            return Exit::InvalidInput;
+        }
+    };
+    let result = async {
+        let mut options = request_options(args)?;
+        options.add_api_host(&site);
+        let favorites = favorites::fetch(&site, typ, &options).await?;
+        anyhow::Ok(favorites)
+    }
+    .await;
+    let favorites = match result {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            return Exit::Failure;
+        }
+    };
+
+    println!("{} {}", "Favorites".green().bold(), favorites.len());
+    for favorite in &favorites {
+        println!(" {}\t{}", favorite.url(&site).purple(), favorite.name);
+    }
+    if list {
+        return Exit::Success;
+    }
+
+    let out = args.out.clone().unwrap_or("favorites".to_string());
+    let shutdown = Arc::new(Shutdown::new());
+    shutdown.listen(cli::on_signal);
+    let mut failed_files = Vec::new();
+    let mut reports = Vec::new();
+    let mut exit = None;
+    for (i, favorite) in favorites.iter().enumerate() {
+        if shutdown.is_requested() {
+            break;
+        }
+        println!(
+            "{} [{}/{}] {}",
+            "Favorite".green().bold(),
+            i + 1,
+            favorites.len(),
+            favorite.name
+        );
+        let Ok(link) = Link::parse(favorite.url(&site)) else {
+            eprintln!("Url is invalid");
+            continue;
+        };
+        let out_dir = format!("{}/{}", out, favorite.folder());
+        // each creator can have its own options in the config.
+        let (args, config) = match resolve_args(command_line, Some(&favorite.url(&site)), None) {
+            Ok(v) => v,
+            Err(err) => {
+                eprintln!("{:#}", err);
+                return Exit::InvalidInput;
+            }
+        };
+        let mut downloader =
+            match create_downloader(&args, &config, link, out_dir, shutdown.clone()) {
+                Ok(downloader) => downloader,
+                Err(err) => {
+                    eprintln!("{:#}", err);
+                    return Exit::InvalidInput;
+                }
+            };
+        failed_files.append(&mut run(&mut downloader).await);
+        notify(&args, &downloader).await;
+        reports.push(downloader.report().await);
+        let run_exit = downloader.exit().await;
+        exit = Some(exit.map_or(run_exit, |exit: Exit| exit.merge(run_exit)));
+    }
+    save_log(args, &failed_files).await;
+    if let Some(path) = &args.report {
+        save_report(path, &json::object! { runs: reports }).await;
+    }
+    exit.unwrap_or(Exit::Success)
+}
+
+/// Download the new posts of every creator of the subscriptions file.
+///
+/// A few creators run at the same time and share the workers, connections and
+/// rate limits of the command line. Pages are fetched again on each run, posts
+/// already downloaded are skipped through the manifest of the creator folder.
+async fn sync(
+    args: &Args,
+    command_line: &CommandLine,
+    config: &Config,
+    file: Option<&str>,
+    creators: usize,
+) -> Exit {
+    let subscriptions = match subscriptions::load(file) {
+        Ok(subscriptions) => subscriptions,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            return Exit::InvalidInput;
+        }
+    };
+    if subscriptions.is_empty() {
+        println!("No creator in the subscriptions file.");
+        return Exit::Success;
+    }
+    let shutdown = Arc::new(Shutdown::new());
+    shutdown.listen(cli::on_signal);
+    let shared = match shared_downloader(args, config, &subscriptions, shutdown.clone()) {
+        Ok(downloader) => downloader,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            return Exit::InvalidInput;
+        }
+    };
+
+    // options of every creator are checked before the first download.
+    let mut downloaders = Vec::new();
+    for subscription in &subscriptions {
+        match subscription_downloader(args, command_line, subscription, &shared) {
+            Ok(v) => downloaders.push(v),
+            Err(err) => {
+                eprintln!("{}: {:#}", subscription.url, err);
+                return Exit::InvalidInput;
+            }
+        }
+    }
+
+    let total = subscriptions.len();
+    let runs = stream::iter(downloaders.into_iter().enumerate())
+        .map(|(i, (args, mut downloader))| {
+            let (shared, url) = (&shared, &subscriptions[i].url);
+            async move {
+                if !shared.shutdown.is_requested() {
+                    println!("{} [{}/{}] {}", "Sync".green().bold(), i + 1, total, url);
+                    cli::print_parameters(&downloader);
+                    if let Err(err) = downloader.all().await {
+                        eprintln!("{}: {}", url, err);
+                    }
+                    notify(&args, &downloader).await;
+                }
+                (i, downloader)
+            }
+        })
+        .buffer_unordered(creators.max(1));
+
+    let mut finished = runs.collect::<Vec<_>>().await;
+
+    // combined report of all creators, in the order of the file.
+    finished.sort_by_key(|(i, _)| *i);
+    println!("{}", "Sync report".green().bold());
+    let mut exit = None;
+    let mut failed_files = Vec::new();
+    let mut reports = Vec::new();
+    let (mut success, mut skipped, mut failed, mut bytes) = (0, 0, 0, 0);
+    for (i, downloader) in &finished {
+        let info = downloader.info.lock().await.clone();
+        let run_exit = downloader.exit().await;
+        exit = Some(exit.map_or(run_exit, |exit: Exit| exit.merge(run_exit)));
+        let run_failed = info.get_failed_file().len() + info.get_failed_post().len();
+        println!(
+            " {}\t{} success, {} skipped, {} failed, {}",
+            subscriptions[*i].url.purple(),
+            info.get_success_file(),
+            info.get_skip_file().len(),
+            run_failed,
+            size::Size::from_bytes(info.get_file_size())
+        );
+        success += info.get_success_file();
+        skipped += info.get_skip_file().len();
+        failed += run_failed;
+        bytes += info.get_file_size();
+        failed_files.append(&mut downloader.failed_file().await);
+        reports.push(downloader.report().await);
+    }
+    if shutdown.is_requested() {
+        println!("{}", "Sync was interrupted.".yellow().bold());
+    }
+    println!("{}: {}", "Creators".blue(), finished.len());
+    println!("{}: {}", "Total size".blue(), size::Size::from_bytes(bytes));
+    println!("{}: {}", "Success files".green(), success);
+    println!("{}: {}", "Skipped files".yellow(), skipped);
+    println!("{}: {}", "Failed files".red(), failed);
+
+    save_log(args, &failed_files).await;
+    if let Some(path) = &args.report {
+        let report = json::object! {
+            creators: finished.len(),
+            bytes: bytes,
+            success_files: success,
+            skipped_files: skipped,
+            failed_files: failed,
+            runs: reports,
+        };
+        save_report(path, &report).await;
+    }
+    exit.unwrap_or(Exit::Success)
+}
+
+/// Poll every creator of the subscriptions file and download new posts, until
+/// Ctrl+C.
+///
+/// Creators are polled one interval after their last poll, a failed poll
+/// doubles the interval of the creator until a poll succeeds again.
+async fn watch(
+    args: &Args,
+    command_line: &CommandLine,
+    config: &Config,
+    file: Option<&str>,
+    creators: usize,
+    schedule: Schedule,
+) -> Exit {
+    let subscriptions = match subscriptions::load(file) {
+        Ok(subscriptions) => subscriptions,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            return Exit::InvalidInput;
+        }
+    };
+    if subscriptions.is_empty() {
+        println!("No creator in the subscriptions file.");
+        return Exit::Success;
+    }
+    let shutdown = Arc::new(Shutdown::new());
+    shutdown.listen(cli::on_signal);
+    let shared = match shared_downloader(args, config, &subscriptions, shutdown.clone()) {
+        Ok(downloader) => downloader,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            return Exit::InvalidInput;
+        }
+    };
+    // options of every creator are checked before the first poll.
+    for subscription in &subscriptions {
+        if let Err(err) = subscription_downloader(args, command_line, subscription, &shared) {
+            eprintln!("{}: {:#}", subscription.url, err);
+            return Exit::InvalidInput;
+        }
+    }
+    println!(
+        "{} {} creators every {}s",
+        "Watch".green().bold(),
+        subscriptions.len(),
+        schedule.interval.as_secs()
+    );
+
+    // each creator has its own schedule, a long download only holds one of the `creators` slots.
+    let slots = Semaphore::new(creators.max(1));
+    let loops = subscriptions.iter().map(|subscription| {
+        let (shared, slots, shutdown) = (&shared, &slots, &shutdown);
+        async move {
+            let mut failures = 0u32;
+            while !shutdown.is_requested() {
+                let polled = {
+                    let Ok(_slot) = (tokio::select! {
+                        slot = slots.acquire() => slot,
+                        _ = shutdown.wait() => break,
+                    }) else {
+                        break;
+                    };
+                    poll_creator(args, command_line, subscription, shared).await
+                };
+                failures = if polled { 0 } else { failures + 1 };
+                let delay = schedule.delay(failures);
+                debug!(target: WATCH, url = subscription.url, failures, delay = delay.as_secs(), "next poll");
+                tokio::select! {
+                    _ = sleep(delay) => {}
+                    _ = shutdown.wait() => {}
+                }
+            }
+        }
+    });
+    join_all(loops).await;
+    Exit::Interrupted
+}
+
+/// Poll a creator and download its new posts, returns `false` when the creator
+/// could not be reached.
+async fn poll_creator(
+    args: &Args,
+    command_line: &CommandLine,
+    subscription: &Subscription,
+    shared: &Downloader,
+) -> bool {
+    let url = subscription.url.as_str();
+    let (args, mut downloader) =
+        match subscription_downloader(args, command_line, subscription, shared) {
+            Ok(v) => v,
+            Err(err) => {
+                eprintln!("{}: {:#}", url, err);
+                return false;
+            }
+        };
+    let new_posts = match downloader.poll().await {
+        Ok(new_posts) => new_posts,
+        Err(err) => {
+            warn!(target: WATCH, url, error = %err, "poll failed");
+            eprintln!("{} {}: {}", "Poll failed".red(), url, err);
+            return false;
+        }
+    };
+    if new_posts.is_empty() {
+        info!(target: WATCH, url, "no new posts");
+        return true;
+    }
+    info!(target: WATCH, url, posts = new_posts.len(), "new posts");
+    println!(
+        "{} {} new posts of {}",
+        "Watch".green().bold(),
+        new_posts.len(),
+        url
+    );
+    // only pages with new posts are fetched.
+    downloader.until_known = true;
+    run(&mut downloader).await;
+    notify(&args, &downloader).await;
+    downloader.exit().await != Exit::Failure
+}
+
+/// Downloader whose workers and limits are shared by every creator of the
+/// subscriptions file.
+fn shared_downloader(
+    args: &Args,
+    config: &Config,
+    subscriptions: &[Subscription],
+    shutdown: Arc<Shutdown>,
+) -> anyhow::Result<Downloader> {
+    let url = subscriptions
+        .first()
+        .map(|subscription| subscription.url.clone())
+        .unwrap_or_default();
+    let link = Link::parse(url)?;
+    create_downloader(args, config, link, ".".to_string(), shutdown)
+}
+
+/// Downloader of a creator of the subscriptions file, with the options of the
+/// entry and the workers and limits of `shared`. The options are returned too.
+///
+/// Creators without `out` get their own folder in the output folder, e.g.
+/// `patreon_12345`.
+fn subscription_downloader(
+    args: &Args,
+    command_line: &CommandLine,
+    subscription: &Subscription,
+    shared: &Downloader,
+) -> anyhow::Result<(Args, Downloader)> {
+    let root = args.out.clone().unwrap_or(".".to_string());
+    let (args, config) = resolve_args(
+        command_line,
+        Some(&subscription.url),
+        Some(&subscription.options),
+    )?;
+    let link = Link::parse(subscription.url.clone())?;
+    let out_dir = subscription.out_dir(&root);
+    let mut downloader = create_downloader(&args, &config, link, out_dir, shared.shutdown.clone())?;
+    downloader.share_limits(shared);
+    Ok((args, downloader))
+}
+
+/// Send the new posts and failures of a run to `--webhook`, an error is only printed.
+async fn notify(args: &Args, downloader: &Downloader) {
+    let Some(url) = &args.webhook else {
+        return;
+    };
+    let Some(notification) = downloader.notification().await else {
+        return;
+    };
+    let webhook = Webhook {
+        url: url.clone(),
+        format: args.webhook_format,
+    };
+    if let Err(err) = webhook.send(&downloader.request, &notification).await {
+        eprintln!("{:#}", err);
+    }
+}
+
+/// Log events are only written with `--verbose` or one of the log options.
+fn init_logging(args: &Args) -> anyhow::Result<()> {
+    let options = LogOptions {
+        file: args.log_file.clone(),
+        level: args
+            .log_level
+            .clone()
+            .or_else(|| args.verbose.then(|| VERBOSE_LEVEL.to_string())),
+        format: args.log_format,
+        rotation: args.log_rotate,
+    };
+    if options.file.is_none() && options.level.is_none() {
+        return Ok(());
+    }
+    options.init(cli::ProgressWriter)
+}
+
+/// File filter from the command line and the categories of the config.
+fn file_filter(args: &Args, config: &Config) -> anyhow::Result<FileFilter> {
+    FileFilter::new(
+        Categories::default().with_config(config),
+        args.only.clone(),
+        args.exclude.clone(),
+        args.ext.clone(),
+        args.exclude_ext.clone(),
+    )
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("invalid date `{}`, expected YYYY-MM-DD", value))
+}
+
+/// Request options from the command line, completed with the config file.
+fn request_options(args: &Args) -> anyhow::Result<RequestOptions> {
+    let mut options = RequestOptions {
+        proxy: args.proxy.clone(),
+        api_proxy: args.api_proxy.clone(),
+        data_proxy: args.data_proxy.clone(),
+        user_agent: args.user_agent.clone(),
+        session: args.session.clone(),
+        ..Default::default()
+    };
+    options.set_timeouts(args.connect_timeout, args.timeout, args.stall_timeout);
+    for header in &args.headers {
+        options.add_header(header)?;
+    }
+    if let Some(cookies) = &args.cookies {
+        options.load_cookies(cookies)?;
     }
+    Ok(options)
 }
@@ -1,12 +1,334 @@
+use anyhow::Context;
+use futures_util::{Stream, StreamExt};
 use reqwest::{
-    Client,
-    header::{HeaderMap, HeaderValue},
+    Client, Proxy, RequestBuilder, Response,
+    cookie::Jar,
+    header::{HeaderMap, HeaderName, HeaderValue},
 };
+use std::{sync::Arc, time::Duration};
+use tokio::time::{error::Elapsed, timeout};
+use url::Url;
 
-pub fn new() -> anyhow::Result<Client> {
+use crate::{config::Config, utils::cookies};
+
+/// Settings applied to every HTTP client, shared by cktool and ckret.
+#[derive(Clone, Debug, Default)]
+pub struct RequestOptions {
+    /// proxy for every request, unless a more specific one below is set.
+    pub proxy: Option<String>,
+    /// proxy for API requests only.
+    pub api_proxy: Option<String>,
+    /// proxy for files on data nodes only.
+    pub data_proxy: Option<String>,
+    /// hosts routed through `api_proxy`, every other host is a data node.
+    pub api_hosts: Vec<String>,
+    /// headers sent with every request, they replace the default ones.
+    pub headers: HeaderMap,
+    pub user_agent: Option<String>,
+    /// value of the `session` cookie of the site, set for every API host.
+    pub session: Option<String>,
+    /// cookies shared by every client, cookies set by the server are kept too.
+    pub cookie_jar: Arc<Jar>,
+    pub connect_timeout: Option<Duration>,
+    /// time to wait for a response, only the headers for files.
+    pub timeout: Option<Duration>,
+    /// time without any received byte before a download is retried.
+    pub stall_timeout: Option<Duration>,
+}
+
+impl RequestOptions {
+    /// Fill options missing from the command line with the config file.
+    pub fn with_config(mut self, config: &Config) -> anyhow::Result<Self> {
+        self.proxy = self.proxy.or_else(|| config.get_str("proxy"));
+        self.api_proxy = self.api_proxy.or_else(|| config.get_str("api-proxy"));
+        self.data_proxy = self.data_proxy.or_else(|| config.get_str("data-proxy"));
+        self.user_agent = self.user_agent.or_else(|| config.get_str("user-agent"));
+        self.session = self.session.or_else(|| config.get_str("session"));
+        // headers from the command line win over the same headers in the config.
+        let headers = std::mem::take(&mut self.headers);
+        for header in config.get_list("header") {
+            self.add_header(&header)?;
+        }
+        self.headers.extend(headers);
+        if let Some(cookies) = config.get_str("cookies") {
+            self.load_cookies(&cookies)?;
+        }
+        Ok(self)
+    }
+
+    /// Add a header given as `Name: value`.
+    pub fn add_header(&mut self, header: &str) -> anyhow::Result<()> {
+        let (name, value) = header
+            .split_once(':')
+            .with_context(|| format!("Invalid header `{}`, expected `Name: value`", header))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .with_context(|| format!("Invalid header name `{}`", name))?;
+        let value = HeaderValue::from_str(value.trim())
+            .with_context(|| format!("Invalid header value `{}`", value))?;
+        self.headers.insert(name, value);
+        Ok(())
+    }
+
+    /// Load cookies from a Netscape `cookies.txt` file into the jar.
+    pub fn load_cookies(&self, path: &str) -> anyhow::Result<usize> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read cookies file {}", path))?;
+        Ok(cookies::load_netscape(&self.cookie_jar, &content))
+    }
+
+    /// Set the timeouts in seconds, zero disables a timeout.
+    pub fn set_timeouts(&mut self, connect: u64, request: u64, stall: u64) {
+        let secs = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+        self.connect_timeout = secs(connect);
+        self.timeout = secs(request);
+        self.stall_timeout = secs(stall);
+    }
+
+    /// Send a request whose body is read in full, e.g. an API request.
+    pub async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        match self.timeout {
+            Some(t) => request.timeout(t).send().await,
+            None => request.send().await,
+        }
+    }
+
+    /// Send a file request, only waiting for the headers is limited.
+    ///
+    /// The body is guarded by the stall timeout instead, see `next_chunk`.
+    pub async fn send_file(&self, request: RequestBuilder) -> anyhow::Result<Response> {
+        let res = match self.timeout {
+            Some(t) => timeout(t, request.send())
+                .await
+                .context("Request timed out")??,
+            None => request.send().await?,
+        };
+        Ok(res)
+    }
+
+    /// Wait for the next chunk of a body, `Err` when the transfer stalled.
+    pub async fn next_chunk<S: Stream + Unpin>(
+        &self,
+        stream: &mut S,
+    ) -> Result<Option<S::Item>, Elapsed> {
+        match self.stall_timeout {
+            Some(t) => timeout(t, stream.next()).await,
+            None => Ok(stream.next().await),
+        }
+    }
+
+    /// Route requests to the host of this url through `api_proxy`.
+    ///
+    /// The `session` cookie is set for this host as well.
+    pub fn add_api_host(&mut self, url: &str) {
+        self.add_session_host(url);
+        if let Some(host) = Url::parse(url).ok().as_ref().and_then(Url::host_str) {
+            self.api_hosts.push(host.to_string());
+        }
+    }
+
+    /// Set the `session` cookie for the host of this url, the proxy routes are unchanged.
+    pub fn add_session_host(&self, url: &str) {
+        let (Some(session), Ok(url)) = (&self.session, Url::parse(url)) else {
+            return;
+        };
+        self.cookie_jar
+            .add_cookie_str(&format!("session={}; Path=/", session), &url);
+    }
+}
+
+/// Parsed proxies, routed by the host of each request.
+struct ProxyRoutes {
+    all: Option<Url>,
+    api: Option<Url>,
+    data: Option<Url>,
+    api_hosts: Vec<String>,
+    env: EnvProxy,
+}
+
+/// Proxies of the `*_PROXY` environment variables, a custom proxy disables
+/// the ones read by reqwest.
+struct EnvProxy {
+    http: Option<Url>,
+    https: Option<Url>,
+    /// hosts of `NO_PROXY`, `*` matches every host.
+    no_proxy: Vec<String>,
+}
+
+impl EnvProxy {
+    fn load() -> Self {
+        let var = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))
+        };
+        let proxy = |names: &[&str]| var(names).and_then(|v| Url::parse(&v).ok());
+        Self {
+            http: proxy(&["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]),
+            https: proxy(&["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]),
+            no_proxy: var(&["NO_PROXY", "no_proxy"])
+                .map(|v| {
+                    v.split(',')
+                        .map(|host| host.trim().trim_start_matches('.').to_lowercase())
+                        .filter(|host| !host.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    fn proxy_for(&self, url: &Url) -> Option<Url> {
+        let host = url.host_str()?;
+        let excluded = self
+            .no_proxy
+            .iter()
+            .any(|no| no == "*" || host == no || host.ends_with(&format!(".{}", no)));
+        if excluded {
+            return None;
+        }
+        match url.scheme() {
+            "https" => self.https.clone(),
+            _ => self.http.clone(),
+        }
+    }
+}
+
+impl ProxyRoutes {
+    fn parse(options: &RequestOptions) -> anyhow::Result<Self> {
+        let parse = |proxy: &Option<String>| {
+            proxy
+                .as_ref()
+                .map(|proxy| {
+                    Url::parse(proxy).with_context(|| format!("Invalid proxy url {}", proxy))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            all: parse(&options.proxy)?,
+            api: parse(&options.api_proxy)?,
+            data: parse(&options.data_proxy)?,
+            api_hosts: options.api_hosts.clone(),
+            env: EnvProxy::load(),
+        })
+    }
+
+    /// Only scheme and host are known here, the path is not passed to proxies.
+    fn proxy_for(&self, url: &Url) -> Option<Url> {
+        let is_api = url
+            .host_str()
+            .is_some_and(|host| self.api_hosts.iter().any(|api| api == host));
+        let specific = if is_api {
+            self.api.as_ref()
+        } else {
+            self.data.as_ref()
+        };
+        specific
+            .or(self.all.as_ref())
+            .cloned()
+            .or_else(|| self.env.proxy_for(url))
+    }
+}
+
+pub fn new(options: &RequestOptions) -> anyhow::Result<Client> {
     let mut headers = HeaderMap::new();
     headers.insert("Accept", HeaderValue::from_static("text/css"));
-    Ok(reqwest::Client::builder()
+    headers.extend(options.headers.clone());
+    let mut builder = reqwest::Client::builder()
         .default_headers(headers)
-        .build()?)
+        .cookie_provider(options.cookie_jar.clone());
+    if let Some(user_agent) = &options.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    if let Some(connect_timeout) = options.connect_timeout {
+        builder = builder.connect_timeout(connect_timeout);
+    }
+
+    // requests without a matching proxy fall back to the `*_PROXY` environment variables.
+    if options.api_proxy.is_some() || options.data_proxy.is_some() {
+        let routes = ProxyRoutes::parse(options)?;
+        builder = builder.proxy(Proxy::custom(move |url| routes.proxy_for(url)));
+    } else if let Some(proxy) = &options.proxy {
+        builder = builder.proxy(Proxy::all(proxy).context("Invalid proxy url")?);
+    }
+
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    fn env(no_proxy: &[&str]) -> EnvProxy {
+        EnvProxy {
+            http: Some(url("http://http-proxy:8080")),
+            https: Some(url("http://https-proxy:8080")),
+            no_proxy: no_proxy.iter().map(|host| host.to_string()).collect(),
+        }
+    }
+
+    fn routes(all: Option<&str>, api: Option<&str>, data: Option<&str>) -> ProxyRoutes {
+        ProxyRoutes {
+            all: all.map(url),
+            api: api.map(url),
+            data: data.map(url),
+            api_hosts: vec!["kemono.cr".to_string()],
+            env: env(&[]),
+        }
+    }
+
+    #[test]
+    fn no_proxy_matches_host_and_subdomains() {
+        let env = env(&["example.com", "localhost"]);
+        assert_eq!(env.proxy_for(&url("https://example.com/a")), None);
+        assert_eq!(env.proxy_for(&url("https://n1.example.com/a")), None);
+        assert_eq!(env.proxy_for(&url("http://localhost:3000")), None);
+        assert_eq!(
+            env.proxy_for(&url("https://notexample.com/a")),
+            Some(url("http://https-proxy:8080"))
+        );
+        assert_eq!(
+            env.proxy_for(&url("http://kemono.cr/a")),
+            Some(url("http://http-proxy:8080"))
+        );
+    }
+
+    #[test]
+    fn no_proxy_star_matches_every_host() {
+        let env = env(&["*"]);
+        assert_eq!(env.proxy_for(&url("https://kemono.cr/api")), None);
+        assert_eq!(env.proxy_for(&url("http://n1.kemono.cr/data")), None);
+    }
+
+    #[test]
+    fn api_and_data_hosts_use_their_own_proxy() {
+        let routes = routes(None, Some("socks5://api:1080"), Some("http://data:3128"));
+        assert_eq!(
+            routes.proxy_for(&url("https://kemono.cr/api/v1/posts")),
+            Some(url("socks5://api:1080"))
+        );
+        assert_eq!(
+            routes.proxy_for(&url("https://n1.kemono.cr/data/a.jpg")),
+            Some(url("http://data:3128"))
+        );
+    }
+
+    #[test]
+    fn missing_route_falls_back_to_proxy_then_env() {
+        let routes = routes(Some("http://all:3128"), Some("socks5://api:1080"), None);
+        assert_eq!(
+            routes.proxy_for(&url("https://n1.kemono.cr/data/a.jpg")),
+            Some(url("http://all:3128"))
+        );
+        let routes = ProxyRoutes {
+            all: None,
+            ..routes
+        };
+        assert_eq!(
+            routes.proxy_for(&url("https://n1.kemono.cr/data/a.jpg")),
+            Some(url("http://https-proxy:8080"))
+        );
+    }
 }
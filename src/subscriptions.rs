@@ -0,0 +1,171 @@
+use anyhow::{Context, Result, anyhow};
+use std::path::PathBuf;
+use toml::Table;
+
+use crate::link::creator_id;
+
+/// A followed creator of the subscriptions file.
+///
+/// ```toml
+/// [[creator]]
+/// url = "https://kemono.cr/fanbox/user/12345"
+/// out = "/data/fanbox"
+/// only = ["image"]
+/// since = "2024-01-01"
+/// ```
+#[derive(Clone, Debug)]
+pub struct Subscription {
+    pub url: String,
+    /// output folder of the creator.
+    pub out: Option<String>,
+    /// other keys, they use the names of the long command line options.
+    pub options: Table,
+}
+
+impl Subscription {
+    /// `out` of the creator, `<root>/<service>_<id>` otherwise.
+    pub fn out_dir(&self, root: &str) -> String {
+        match (&self.out, creator_id(&self.url)) {
+            (Some(out), _) => out.clone(),
+            (None, Some((service, id))) => format!("{}/{}_{}", root, service, id),
+            (None, None) => root.to_string(),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/cktool/subscriptions.toml` or the platform equivalent.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("cktool").join("subscriptions.toml"))
+}
+
+/// Load the creators of the subscriptions file.
+///
+/// # Arguments
+/// * `path` - file given by `--file`, the default file otherwise.
+pub fn load(path: Option<&str>) -> Result<Vec<Subscription>> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => default_path().context("Cannot find the config folder")?,
+    };
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Cannot read subscriptions file {}", path.display()))?;
+    let table = content
+        .parse::<Table>()
+        .with_context(|| format!("Cannot parse subscriptions file {}", path.display()))?;
+    let Some(creators) = table.get("creator").and_then(|v| v.as_array()) else {
+        return Ok(Vec::new());
+    };
+    creators
+        .iter()
+        .enumerate()
+        .map(|(i, creator)| {
+            let mut options = creator
+                .as_table()
+                .cloned()
+                .ok_or_else(|| anyhow!("Creator {} of {} is not a table", i + 1, path.display()))?;
+            let url = match options.remove("url") {
+                Some(toml::Value::String(url)) => url,
+                _ => {
+                    return Err(anyhow!(
+                        "Creator {} of {} has no url",
+                        i + 1,
+                        path.display()
+                    ));
+                }
+            };
+            let out = match options.remove("out") {
+                Some(toml::Value::String(out)) => Some(out),
+                _ => None,
+            };
+            Ok(Subscription { url, out, options })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_str(name: &str, content: &str) -> Result<Vec<Subscription>> {
+        let path = std::env::temp_dir().join(format!(
+            "cktool-subscriptions-{}-{}.toml",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, content).unwrap();
+        let subscriptions = load(path.to_str());
+        std::fs::remove_file(&path).unwrap();
+        subscriptions
+    }
+
+    #[test]
+    fn creators_with_their_options() {
+        let subscriptions = load_str(
+            "creators",
+            r#"
+            [[creator]]
+            url = "https://kemono.cr/fanbox/user/12345"
+            out = "/data/fanbox"
+            only = ["image"]
+            since = "2024-01-01"
+
+            [[creator]]
+            url = "https://coomer.st/onlyfans/user/someone"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(subscriptions.len(), 2);
+        let first = &subscriptions[0];
+        assert_eq!(first.url, "https://kemono.cr/fanbox/user/12345");
+        assert_eq!(first.out.as_deref(), Some("/data/fanbox"));
+        // `url` and `out` are not passed as options.
+        assert_eq!(first.options.keys().collect::<Vec<_>>(), ["only", "since"]);
+        assert!(subscriptions[1].out.is_none());
+        assert!(subscriptions[1].options.is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_are_kept_for_the_config_check() {
+        let subscriptions = load_str(
+            "unknown",
+            r#"
+            [[creator]]
+            url = "https://kemono.cr/fanbox/user/12345"
+            tsak = 4
+            "#,
+        )
+        .unwrap();
+        // rejected with the other options by `Config::to_args`.
+        assert_eq!(
+            subscriptions[0].options.get("tsak"),
+            Some(&toml::Value::Integer(4))
+        );
+    }
+
+    #[test]
+    fn default_out_folder_per_creator() {
+        let subscription = |url: &str, out: Option<&str>| Subscription {
+            url: url.to_string(),
+            out: out.map(str::to_string),
+            options: Table::new(),
+        };
+        assert_eq!(
+            subscription("https://kemono.cr/fanbox/user/12345", None).out_dir("/data"),
+            "/data/fanbox_12345"
+        );
+        assert_eq!(
+            subscription("https://kemono.cr/fanbox/user/12345", Some("/nas/a")).out_dir("/data"),
+            "/nas/a"
+        );
+    }
+
+    #[test]
+    fn invalid_creators() {
+        let err = load_str("no-url", "[[creator]]\nout = \"a\"\n").unwrap_err();
+        assert!(err.to_string().contains("Creator 1 of"), "{}", err);
+        assert!(err.to_string().ends_with("has no url"), "{}", err);
+        assert!(load_str("not-table", "creator = [1]\n").is_err());
+        assert!(load_str("empty", "").unwrap().is_empty());
+        assert!(load_str("broken", "[[creator]\n").is_err());
+    }
+}
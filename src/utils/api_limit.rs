@@ -0,0 +1,153 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
+
+use crate::declare::{API_MIN_RPS, API_RPS_DECREASE_INTERVAL_SEC};
+
+struct State {
+    /// current requests per second, lowered by 429 responses.
+    rate: f64,
+    /// requests which can be sent right now, negative while in debt.
+    tokens: f64,
+    last: Instant,
+    last_decrease: Option<Instant>,
+}
+
+/// Token bucket for API requests, shared by all workers.
+///
+/// The rate is halved when the server answers "too many requests" and
+/// recovers slowly on every successful request, up to `max_rate`.
+pub struct ApiLimiter {
+    max_rate: f64,
+    state: Mutex<State>,
+}
+
+impl ApiLimiter {
+    pub fn new(max_rate: f64) -> Self {
+        let max_rate = max_rate.max(API_MIN_RPS);
+        Self {
+            max_rate,
+            state: Mutex::new(State {
+                rate: max_rate,
+                tokens: 1.0,
+                last: Instant::now(),
+                last_decrease: None,
+            }),
+        }
+    }
+
+    pub fn max_rate(&self) -> f64 {
+        self.max_rate
+    }
+
+    pub fn current_rate(&self) -> f64 {
+        self.state.lock().unwrap().rate
+    }
+
+    /// Wait until the next API request is allowed.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(state.last).as_secs_f64();
+            state.last = now;
+            // one token of burst, requests are spread evenly.
+            state.tokens = (state.tokens + elapsed * state.rate).min(1.0);
+            state.tokens -= 1.0;
+            if state.tokens < 0.0 {
+                Duration::from_secs_f64(-state.tokens / state.rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+
+    /// The server answered 429, halve the rate.
+    ///
+    /// Workers which were already waiting report the same burst, so the rate
+    /// is lowered at most once per `API_RPS_DECREASE_INTERVAL_SEC`.
+    pub fn too_many_requests(&self) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if let Some(last) = state.last_decrease
+            && now.duration_since(last) < Duration::from_secs(API_RPS_DECREASE_INTERVAL_SEC)
+        {
+            return;
+        }
+        state.last_decrease = Some(now);
+        state.rate = (state.rate / 2.0).max(API_MIN_RPS);
+    }
+
+    /// A request succeeded, recover 1% of the maximum rate.
+    pub fn success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.rate = (state.rate + self.max_rate / 100.0).min(self.max_rate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Let the next 429 lower the rate again.
+    fn after_decrease_interval(limiter: &ApiLimiter) {
+        let mut state = limiter.state.lock().unwrap();
+        state.last_decrease = state
+            .last_decrease
+            .map(|last| last - Duration::from_secs(API_RPS_DECREASE_INTERVAL_SEC));
+    }
+
+    #[test]
+    fn too_many_requests_halves_the_rate_once_per_burst() {
+        let limiter = ApiLimiter::new(10.0);
+        limiter.too_many_requests();
+        assert_eq!(limiter.current_rate(), 5.0);
+        // other workers reporting the same burst.
+        limiter.too_many_requests();
+        assert_eq!(limiter.current_rate(), 5.0);
+        after_decrease_interval(&limiter);
+        limiter.too_many_requests();
+        assert_eq!(limiter.current_rate(), 2.5);
+    }
+
+    #[test]
+    fn rate_never_drops_below_the_floor() {
+        let limiter = ApiLimiter::new(2.0);
+        for _ in 0..10 {
+            limiter.too_many_requests();
+            after_decrease_interval(&limiter);
+        }
+        assert_eq!(limiter.current_rate(), API_MIN_RPS);
+        // the maximum is raised to the floor too.
+        assert_eq!(ApiLimiter::new(0.0).max_rate(), API_MIN_RPS);
+    }
+
+    #[test]
+    fn success_recovers_slowly_up_to_the_maximum() {
+        let limiter = ApiLimiter::new(10.0);
+        limiter.too_many_requests();
+        limiter.success();
+        assert!((limiter.current_rate() - 5.1).abs() < 1e-9);
+        for _ in 0..48 {
+            limiter.success();
+        }
+        assert!(limiter.current_rate() < 10.0);
+        for _ in 0..10 {
+            limiter.success();
+        }
+        assert_eq!(limiter.current_rate(), 10.0);
+    }
+
+    #[tokio::test]
+    async fn first_request_does_not_wait() {
+        let limiter = ApiLimiter::new(1.0);
+        let started = Instant::now();
+        limiter.acquire().await;
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+}
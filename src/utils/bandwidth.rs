@@ -0,0 +1,179 @@
+use chrono::{Local, NaiveTime};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
+
+use super::parse_size;
+
+/// A time of day window with its own rate, `None` means unlimited.
+#[derive(Clone, Debug)]
+pub struct ScheduleWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+    rate: Option<u64>,
+}
+
+impl ScheduleWindow {
+    /// Windows may wrap around midnight, e.g. `22:00-06:00`.
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+/// List of time of day windows which change the bandwidth limit.
+#[derive(Clone, Debug, Default)]
+pub struct RateSchedule(Vec<ScheduleWindow>);
+
+impl RateSchedule {
+    /// Parse a schedule like `01:00-07:00=unlimited,18:00-23:00=1M`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut windows = Vec::new();
+        for window in value.split(',').map(str::trim).filter(|w| !w.is_empty()) {
+            let invalid = || format!("invalid schedule `{}`, expected HH:MM-HH:MM=RATE", window);
+            let (range, rate) = window.split_once('=').ok_or_else(invalid)?;
+            let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+            let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").map_err(|_| invalid())?;
+            let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").map_err(|_| invalid())?;
+            let rate = match rate.trim() {
+                "unlimited" | "0" => None,
+                rate => Some(parse_size(rate)?),
+            };
+            windows.push(ScheduleWindow { start, end, rate });
+        }
+        Ok(Self(windows))
+    }
+}
+
+struct Bucket {
+    /// bytes which can be sent right now, negative while in debt.
+    available: f64,
+    last: Instant,
+}
+
+/// Global bandwidth limiter shared by every download task.
+///
+/// Works as a token bucket of one second, each chunk takes its size from the
+/// bucket and waits until the debt is paid back.
+pub struct Bandwidth {
+    rate: Option<u64>,
+    schedule: RateSchedule,
+    bucket: Mutex<Bucket>,
+}
+
+impl Bandwidth {
+    pub fn new(rate: Option<u64>, schedule: RateSchedule) -> Self {
+        Self {
+            rate,
+            schedule,
+            bucket: Mutex::new(Bucket {
+                available: 0.0,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(None, RateSchedule::default())
+    }
+
+    /// Current rate in bytes per second, the schedule wins over `rate`.
+    pub fn current_rate(&self) -> Option<u64> {
+        let now = Local::now().time();
+        match self.schedule.0.iter().find(|w| w.contains(now)) {
+            Some(window) => window.rate,
+            None => self.rate,
+        }
+    }
+
+    /// Take `bytes` from the bucket, sleeps when the limit is reached.
+    pub async fn consume(&self, bytes: usize) {
+        let Some(rate) = self.current_rate().filter(|rate| *rate > 0) else {
+            return;
+        };
+        let rate = rate as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last).as_secs_f64();
+            bucket.last = now;
+            bucket.available = (bucket.available + elapsed * rate).min(rate);
+            bucket.available -= bytes as f64;
+            if bucket.available < 0.0 {
+                Duration::from_secs_f64(-bucket.available / rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+}
+
+impl Default for Bandwidth {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn parse_windows() {
+        let schedule = RateSchedule::parse("01:00-07:00=unlimited, 18:00-23:00=1M,").unwrap();
+        assert_eq!(schedule.0.len(), 2);
+        assert_eq!(schedule.0[0].rate, None);
+        assert_eq!(schedule.0[1].rate, Some(1 << 20));
+        assert!(schedule.0[1].contains(time("18:00")));
+        assert!(!schedule.0[1].contains(time("23:00")));
+        assert!(!schedule.0[1].contains(time("12:00")));
+        assert_eq!(
+            RateSchedule::parse("00:00-01:00=0").unwrap().0[0].rate,
+            None
+        );
+        assert!(RateSchedule::parse("").unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn window_wraps_around_midnight() {
+        let schedule = RateSchedule::parse("22:00-06:00=500K").unwrap();
+        let window = &schedule.0[0];
+        assert!(window.contains(time("22:00")));
+        assert!(window.contains(time("23:59")));
+        assert!(window.contains(time("00:00")));
+        assert!(window.contains(time("05:59")));
+        assert!(!window.contains(time("06:00")));
+        assert!(!window.contains(time("12:00")));
+    }
+
+    #[test]
+    fn malformed_schedules_are_rejected() {
+        for value in [
+            "01:00-07:00",
+            "01:00=1M",
+            "1-7=1M",
+            "25:00-07:00=1M",
+            "01:00-07:60=1M",
+            "01:00-07:00=fast",
+            "01:00-07:00=1M,bad",
+        ] {
+            assert!(
+                RateSchedule::parse(value).is_err(),
+                "`{}` should be rejected",
+                value
+            );
+        }
+    }
+}
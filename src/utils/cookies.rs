@@ -0,0 +1,98 @@
+use chrono::Utc;
+use reqwest::cookie::Jar;
+use url::Url;
+
+/// Add the cookies of a Netscape `cookies.txt` file to the jar.
+///
+/// Returns the number of cookies added, expired cookies are ignored.
+pub fn load_netscape(jar: &Jar, content: &str) -> usize {
+    let now = Utc::now().timestamp();
+    let mut count = 0;
+    for line in content.lines() {
+        // `#HttpOnly_` prefixed lines are cookies, other `#` lines are comments.
+        let line = line.trim_end_matches('\r');
+        let line = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => line,
+            None if line.starts_with('#') || line.trim().is_empty() => continue,
+            None => line,
+        };
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        let [
+            domain,
+            include_subdomains,
+            path,
+            secure,
+            expires,
+            name,
+            value,
+        ] = fields[..]
+        else {
+            continue;
+        };
+        if let Ok(expires) = expires.parse::<i64>()
+            && expires != 0
+            && expires < now
+        {
+            continue;
+        }
+
+        let host = domain.trim_start_matches('.');
+        let Ok(url) = Url::parse(&format!("https://{}{}", host, path)) else {
+            continue;
+        };
+        let mut cookie = format!("{}={}; Path={}", name, value, path);
+        if include_subdomains == "TRUE" {
+            cookie.push_str(&format!("; Domain={}", host));
+        }
+        if secure == "TRUE" {
+            cookie.push_str("; Secure");
+        }
+        jar.add_cookie_str(&cookie, &url);
+        count += 1;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::cookie::CookieStore;
+
+    fn cookies(jar: &Jar, url: &str) -> String {
+        jar.cookies(&Url::parse(url).unwrap())
+            .map(|value| value.to_str().unwrap().to_string())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn comments_and_short_lines_are_ignored() {
+        let jar = Jar::default();
+        let content = "# Netscape HTTP Cookie File\r\n\
+                       \n\
+                       #kemono.cr\tFALSE\t/\tFALSE\t0\tcommented\tout\n\
+                       kemono.cr\tFALSE\t/\tFALSE\t0\tshort\n\
+                       kemono.cr\tFALSE\t/\tFALSE\t1\texpired\tyes\n\
+                       kemono.cr\tFALSE\t/\tFALSE\t0\tsession\tabc\r\n\
+                       #HttpOnly_kemono.cr\tFALSE\t/\tFALSE\t0\thttponly\tdef\n";
+        assert_eq!(load_netscape(&jar, content), 2);
+        let sent = cookies(&jar, "https://kemono.cr/");
+        assert!(sent.contains("session=abc"), "{}", sent);
+        assert!(sent.contains("httponly=def"), "{}", sent);
+        assert!(!sent.contains("commented") && !sent.contains("short"));
+        assert!(!sent.contains("expired"));
+    }
+
+    #[test]
+    fn domain_and_secure_fields() {
+        let jar = Jar::default();
+        let content = ".coomer.st\tTRUE\t/\tTRUE\t0\tshared\t1\n\
+                       kemono.cr\tFALSE\t/\tFALSE\t0\thost\t2\n";
+        assert_eq!(load_netscape(&jar, content), 2);
+        // a domain cookie is sent to subdomains, only over https when secure.
+        assert_eq!(cookies(&jar, "https://n1.coomer.st/data"), "shared=1");
+        assert_eq!(cookies(&jar, "http://coomer.st/"), "");
+        // a host cookie is not sent to subdomains, over http when not secure.
+        assert_eq!(cookies(&jar, "http://kemono.cr/"), "host=2");
+        assert_eq!(cookies(&jar, "https://n1.kemono.cr/"), "");
+    }
+}
@@ -1,3 +1,14 @@
+mod api_limit;
+mod bandwidth;
+pub mod cookies;
 mod log;
+mod shutdown;
+pub mod size;
+mod sniff;
 
+pub use api_limit::ApiLimiter;
+pub use bandwidth::{Bandwidth, RateSchedule};
 pub use log::Log;
+pub use shutdown::{Shutdown, Signal};
+pub use size::parse_size;
+pub use sniff::{SNIFF_BYTES, check_magic, read_head};
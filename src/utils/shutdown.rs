@@ -0,0 +1,87 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use tokio::sync::Notify;
+
+/// Signal received by `Shutdown::listen`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    /// first signal, the shutdown was requested.
+    Stop,
+    /// second signal while stopping.
+    Abort,
+}
+
+/// Graceful shutdown requested by SIGINT/SIGTERM.
+///
+/// The first signal only sets a flag, workers stop taking new posts and
+/// running downloads flush what they already received. A second signal
+/// aborts at once.
+#[derive(Default)]
+pub struct Shutdown {
+    requested: AtomicBool,
+    notify: Notify,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Resolves once shutdown was requested.
+    pub async fn wait(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if self.is_requested() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Listen for SIGINT/SIGTERM in the background, `on_signal` is told about each one.
+    ///
+    /// The first signal requests the shutdown, aborting on the second one is
+    /// left to `on_signal`.
+    pub fn listen(self: &Arc<Self>, on_signal: impl Fn(Signal) + Send + 'static) {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            loop {
+                if wait_signal().await.is_err() {
+                    return;
+                }
+                if shutdown.is_requested() {
+                    on_signal(Signal::Abort);
+                    return;
+                }
+                shutdown.request();
+                on_signal(Signal::Stop);
+            }
+        });
+    }
+}
+
+#[cfg(unix)]
+async fn wait_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
@@ -0,0 +1,60 @@
+use size::Size;
+
+/// Parse a size from the command line.
+///
+/// Single letter units (`500K`, `5M`, `1.5G`) are binary like curl's
+/// `--limit-rate`, anything else (`100 MB`, `2 GiB`, `1234`) is handled by
+/// the `size` crate.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let multiplier = match value.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => Some(1u64 << 10),
+        Some('M') => Some(1u64 << 20),
+        Some('G') => Some(1u64 << 30),
+        Some('T') => Some(1u64 << 40),
+        _ => None,
+    };
+    if let Some(multiplier) = multiplier
+        && let Ok(number) = value[..value.len() - 1].trim().parse::<f64>()
+        && number.is_finite()
+        && number >= 0.0
+    {
+        return Ok((number * multiplier as f64) as u64);
+    }
+    Size::from_str(value)
+        .ok()
+        .filter(|size| size.bytes() >= 0)
+        .map(|size| size.bytes() as u64)
+        .ok_or_else(|| format!("invalid size `{}`, expected e.g. 500K, 5M or 1.5G", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_letter_units_are_binary() {
+        assert_eq!(parse_size("500K"), Ok(500 << 10));
+        assert_eq!(parse_size("5m"), Ok(5 << 20));
+        assert_eq!(parse_size("1.5G"), Ok(3 << 29));
+        assert_eq!(parse_size("2T"), Ok(2 << 40));
+        assert_eq!(parse_size(" 10 M "), Ok(10 << 20));
+    }
+
+    #[test]
+    fn other_units_and_plain_bytes() {
+        assert_eq!(parse_size("1234"), Ok(1234));
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("100 MB"), Ok(100_000_000));
+        assert_eq!(parse_size("2 GiB"), Ok(2 << 30));
+    }
+
+    #[test]
+    fn malformed_sizes_are_rejected() {
+        for value in [
+            "", "K", "M5", "abc", "5X", "-5M", "-1", "NaNK", "infM", "1..5G",
+        ] {
+            assert!(parse_size(value).is_err(), "`{}` should be rejected", value);
+        }
+    }
+}
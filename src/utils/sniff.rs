@@ -0,0 +1,228 @@
+use tokio::io::AsyncReadExt;
+
+use crate::category::extension;
+
+/// Number of bytes at the start of a body needed to check its type.
+pub const SNIFF_BYTES: usize = 64;
+
+/// Text files which may legitimately start like an HTML page.
+const TEXT_EXTENSIONS: &[&str] = &["html", "htm", "xml", "svg", "txt", "md", "json"];
+
+/// Check the first bytes of a body against the kind of file expected for the file name.
+///
+/// Returns the reason of a mismatch, e.g. an HTML error page or a DDoS
+/// protection challenge served with status 200 instead of the media file.
+/// Files of unknown type only fail when they look like an HTML page.
+pub fn check_magic(fname: &str, head: &[u8]) -> Result<(), String> {
+    let ext = extension(fname).unwrap_or_default();
+    if TEXT_EXTENSIONS.contains(&ext.as_str()) {
+        return Ok(());
+    }
+    if is_html(head) {
+        return Err(format!("HTML page instead of {}", kind(&ext)));
+    }
+    match expected(&ext) {
+        Some(signatures) if !head.is_empty() && !signatures.iter().any(|matches| matches(head)) => {
+            Err(format!("content is not {}", kind(&ext)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// First `SNIFF_BYTES` of a file on disk, fewer when the file is shorter.
+pub async fn read_head(path: &str) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    tokio::fs::File::open(path)
+        .await?
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut head)
+        .await?;
+    Ok(head)
+}
+
+fn kind(ext: &str) -> &str {
+    if ext.is_empty() {
+        "the expected file"
+    } else {
+        ext
+    }
+}
+
+fn is_html(head: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(head);
+    let text = text
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .to_lowercase();
+    [
+        "<!doctype html",
+        "<html",
+        "<head",
+        "<body",
+        "<script",
+        "<!--",
+    ]
+    .iter()
+    .any(|tag| text.starts_with(tag))
+}
+
+/// Check of the first bytes of a file type.
+type Signature = fn(&[u8]) -> bool;
+
+/// Signatures accepted for a file of a known type.
+///
+/// Uploads often have the extension of another type of the same kind, e.g. a
+/// PNG saved as `.jpg` or an MP4 saved as `.mov`, so any signature of the
+/// kind is accepted.
+fn expected(ext: &str) -> Option<&'static [Signature]> {
+    let signatures: &[Signature] = match ext {
+        "jpg" | "jpeg" | "jfif" | "png" | "gif" | "webp" | "bmp" | "heic" | "avif" => {
+            &[jpeg, png, gif, webp, bmp, iso_media]
+        }
+        "mp4" | "m4v" | "mov" | "webm" | "mkv" | "avi" | "flv" => &[iso_media, ebml, riff, flv],
+        "mp3" | "m4a" | "wav" | "flac" | "ogg" | "opus" => &[mp3, iso_media, riff, flac, ogg],
+        "zip" | "rar" | "7z" | "gz" => &[zip, rar, seven_zip, gzip],
+        "pdf" | "docx" | "xlsx" | "pptx" | "epub" => &[pdf, zip],
+        "psd" | "kra" => &[psd, zip],
+        _ => return None,
+    };
+    Some(signatures)
+}
+
+fn jpeg(h: &[u8]) -> bool {
+    h.starts_with(&[0xFF, 0xD8, 0xFF])
+}
+
+fn png(h: &[u8]) -> bool {
+    h.starts_with(&[0x89, b'P', b'N', b'G'])
+}
+
+fn gif(h: &[u8]) -> bool {
+    h.starts_with(b"GIF8")
+}
+
+fn webp(h: &[u8]) -> bool {
+    h.starts_with(b"RIFF") && h.get(8..12) == Some(b"WEBP")
+}
+
+fn bmp(h: &[u8]) -> bool {
+    h.starts_with(b"BM")
+}
+
+/// MP4, MOV, M4A, HEIC and AVIF.
+fn iso_media(h: &[u8]) -> bool {
+    matches!(
+        h.get(4..8),
+        Some(b"ftyp" | b"moov" | b"mdat" | b"wide" | b"free" | b"skip")
+    )
+}
+
+/// WebM and MKV.
+fn ebml(h: &[u8]) -> bool {
+    h.starts_with(&[0x1A, 0x45, 0xDF, 0xA3])
+}
+
+/// AVI and WAV.
+fn riff(h: &[u8]) -> bool {
+    h.starts_with(b"RIFF")
+}
+
+fn flv(h: &[u8]) -> bool {
+    h.starts_with(b"FLV")
+}
+
+fn mp3(h: &[u8]) -> bool {
+    h.starts_with(b"ID3") || (h.len() > 1 && h[0] == 0xFF && h[1] & 0xE0 == 0xE0)
+}
+
+fn flac(h: &[u8]) -> bool {
+    h.starts_with(b"fLaC")
+}
+
+fn ogg(h: &[u8]) -> bool {
+    h.starts_with(b"OggS")
+}
+
+/// ZIP and the formats built on it, e.g. DOCX, EPUB and KRA.
+fn zip(h: &[u8]) -> bool {
+    h.starts_with(b"PK")
+}
+
+fn rar(h: &[u8]) -> bool {
+    h.starts_with(b"Rar!")
+}
+
+fn seven_zip(h: &[u8]) -> bool {
+    h.starts_with(&[b'7', b'z', 0xBC, 0xAF])
+}
+
+fn gzip(h: &[u8]) -> bool {
+    h.starts_with(&[0x1F, 0x8B])
+}
+
+fn pdf(h: &[u8]) -> bool {
+    h.starts_with(b"%PDF")
+}
+
+fn psd(h: &[u8]) -> bool {
+    h.starts_with(b"8BPS")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTML: &[u8] = b"<!DOCTYPE html><html><head><title>Just a moment...</title>";
+
+    #[test]
+    fn html_instead_of_media() {
+        assert!(check_magic("a.jpg", HTML).is_err());
+        assert!(check_magic("a.mp4", b"  \n<html lang=\"en\">").is_err());
+        assert!(check_magic("a.zip", "\u{feff}<!-- blocked -->".as_bytes()).is_err());
+        // unknown types only fail on HTML.
+        assert!(check_magic("a.clip", HTML).is_err());
+        assert!(check_magic("noext", HTML).is_err());
+        assert!(check_magic("a.clip", b"anything").is_ok());
+    }
+
+    #[test]
+    fn known_signatures() {
+        assert!(check_magic("a.jpg", &[0xFF, 0xD8, 0xFF, 0xE0]).is_ok());
+        assert!(check_magic("a.JPEG", &[0xFF, 0xD8, 0xFF, 0xE0]).is_ok());
+        assert!(check_magic("a.mp4", b"\x00\x00\x00\x18ftypmp42").is_ok());
+        assert!(check_magic("a.webp", b"RIFF\x10\x00\x00\x00WEBPVP8 ").is_ok());
+        assert!(check_magic("a.psd", b"8BPS").is_ok());
+        assert!(check_magic("a.webp", b"RIFF\x10\x00\x00\x00AVI ").is_err());
+    }
+
+    #[test]
+    fn signatures_of_the_same_kind() {
+        // images, videos and archives saved with the extension of another type.
+        assert!(check_magic("a.jpg", b"\x89PNG\r\n").is_ok());
+        assert!(check_magic("a.jpg", b"RIFF\x10\x00\x00\x00WEBPVP8 ").is_ok());
+        assert!(check_magic("a.mov", b"\x00\x00\x00\x18ftypmp42").is_ok());
+        assert!(check_magic("a.mp4", &[0x1A, 0x45, 0xDF, 0xA3]).is_ok());
+        assert!(check_magic("a.zip", b"Rar!\x1A\x07").is_ok());
+        // the ISO media signature is shared by HEIC and AVIF images.
+        assert!(check_magic("a.jpg", b"\x00\x00\x00\x18ftypavif").is_ok());
+        // another kind is still rejected.
+        assert!(check_magic("a.png", b"PK\x03\x04").is_err());
+        assert!(check_magic("a.mp4", b"\x89PNG\r\n").is_err());
+        assert!(check_magic("a.pdf", b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn text_files_may_be_html() {
+        assert!(check_magic("page.html", HTML).is_ok());
+        assert!(check_magic("image.svg", b"<!-- comment --><svg>").is_ok());
+    }
+
+    #[test]
+    fn short_heads() {
+        // nothing to check yet.
+        assert!(check_magic("a.jpg", b"").is_ok());
+        // too short for the signature.
+        assert!(check_magic("a.mp4", b"\x00\x00").is_err());
+        assert!(check_magic("a.jpg", b"<html").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+/// When each creator of `cktool watch` is polled again.
+#[derive(Clone, Copy, Debug)]
+pub struct Schedule {
+    /// time between two polls of a creator.
+    pub interval: Duration,
+    /// random delay up to this long added to each poll, so creators are not
+    /// all polled at the same time.
+    pub jitter: Duration,
+    /// longest time between two polls of a failing creator.
+    pub max_backoff: Duration,
+}
+
+impl Schedule {
+    /// Delay before the next poll of a creator.
+    ///
+    /// # Arguments
+    /// * `failures` - number of polls failed in a row, each one doubles the
+    ///   interval up to `max_backoff`.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.min(16));
+        let delay = if failures == 0 {
+            self.interval
+        } else {
+            self.interval
+                .saturating_mul(factor)
+                .min(self.max_backoff.max(self.interval))
+        };
+        delay + random_up_to(self.jitter)
+    }
+}
+
+fn random_up_to(max: Duration) -> Duration {
+    let max = max.as_millis() as u64;
+    if max == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(fastrand::u64(0..=max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEDULE: Schedule = Schedule {
+        interval: Duration::from_secs(60),
+        jitter: Duration::from_secs(10),
+        max_backoff: Duration::from_secs(600),
+    };
+
+    #[test]
+    fn delay_stays_within_interval_and_backoff() {
+        for failures in 0..40 {
+            for _ in 0..50 {
+                let delay = SCHEDULE.delay(failures);
+                assert!(delay >= SCHEDULE.interval, "{:?} at {}", delay, failures);
+                assert!(
+                    delay <= SCHEDULE.max_backoff + SCHEDULE.jitter,
+                    "{:?} at {}",
+                    delay,
+                    failures
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn failures_double_the_interval_up_to_max_backoff() {
+        let schedule = Schedule {
+            jitter: Duration::ZERO,
+            ..SCHEDULE
+        };
+        let delays = (0..6)
+            .map(|f| schedule.delay(f).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [60, 120, 240, 480, 600, 600]);
+        assert_eq!(schedule.delay(u32::MAX), Duration::from_secs(600));
+    }
+
+    #[test]
+    fn interval_wins_over_a_shorter_max_backoff() {
+        let schedule = Schedule {
+            jitter: Duration::ZERO,
+            max_backoff: Duration::from_secs(10),
+            ..SCHEDULE
+        };
+        assert_eq!(schedule.delay(3), SCHEDULE.interval);
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::{Context, bail};
+use json::{JsonValue, object};
+use reqwest::header::CONTENT_TYPE;
+
+use crate::request::RequestOptions;
+
+/// Most new posts listed by title in a chat message.
+const LISTED_POSTS: usize = 10;
+/// Longest message accepted by a Discord webhook.
+const DISCORD_MAX_CHARS: usize = 2000;
+
+/// Body sent to the webhook.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// the notification as it is, for scripts and automation tools.
+    #[default]
+    Json,
+    /// a message for a Discord channel webhook.
+    Discord,
+    /// a message for a Slack incoming webhook, Mattermost accepts it too.
+    Slack,
+}
+
+impl WebhookFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "json" => Ok(WebhookFormat::Json),
+            "discord" => Ok(WebhookFormat::Discord),
+            "slack" => Ok(WebhookFormat::Slack),
+            _ => Err(format!(
+                "invalid webhook format `{}`, use json, discord or slack",
+                value
+            )),
+        }
+    }
+}
+
+/// HTTP endpoint notified of new posts and failed runs.
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    pub url: String,
+    pub format: WebhookFormat,
+}
+
+impl Webhook {
+    /// POST a notification, see `Downloader::notification`.
+    ///
+    /// Only the timeouts of `options` are used: the headers, cookies and
+    /// proxies of the site are never sent to the webhook.
+    pub async fn send(
+        &self,
+        options: &RequestOptions,
+        notification: &JsonValue,
+    ) -> anyhow::Result<()> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(connect_timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        let client = builder.build()?;
+        let request = client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(json::stringify(self.body(notification)));
+        let res = options
+            .send(request)
+            .await
+            .with_context(|| format!("Cannot send webhook {}", self.url))?;
+        if !res.status().is_success() {
+            bail!("Webhook {} answered {}", self.url, res.status());
+        }
+        Ok(())
+    }
+
+    fn body(&self, notification: &JsonValue) -> JsonValue {
+        match self.format {
+            WebhookFormat::Json => notification.clone(),
+            WebhookFormat::Discord => object! {
+                content: message(notification).chars().take(DISCORD_MAX_CHARS).collect::<String>(),
+            },
+            WebhookFormat::Slack => object! { text: message(notification) },
+        }
+    }
+}
+
+/// Text of a chat message: the creator, its new posts and the file counts.
+fn message(notification: &JsonValue) -> String {
+    let creator = &notification["creator"];
+    let name = creator["name"]
+        .as_str()
+        .or(creator["url"].as_str())
+        .unwrap_or_default();
+    let new_posts = &notification["new_posts"];
+    let mut lines = Vec::new();
+    if new_posts.is_empty() {
+        lines.push(format!("{}: run finished with failures", name));
+    } else {
+        lines.push(format!("{}: {} new posts", name, new_posts.len()));
+        for post in new_posts.members().take(LISTED_POSTS) {
+            let title = post["title"]
+                .as_str()
+                .filter(|title| !title.is_empty())
+                .or(post["id"].as_str())
+                .unwrap_or_default();
+            lines.push(format!("- {} <{}>", title, post["url"]));
+        }
+        if new_posts.len() > LISTED_POSTS {
+            lines.push(format!("and {} more", new_posts.len() - LISTED_POSTS));
+        }
+    }
+    let files = &notification["files"];
+    lines.push(format!(
+        "{} files downloaded, {} skipped, {} failed",
+        files["success"], files["skipped"], files["failed"]
+    ));
+    if let Some(error) = notification["error"].as_str() {
+        lines.push(format!("Error: {}", error));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhook(format: WebhookFormat) -> Webhook {
+        Webhook {
+            url: "http://localhost/hook".to_string(),
+            format,
+        }
+    }
+
+    fn notification(new_posts: usize) -> JsonValue {
+        let posts = (1..=new_posts)
+            .map(|id| {
+                object! {
+                    id: id.to_string(),
+                    title: if id == 2 { String::new() } else { format!("post {}", id) },
+                    url: format!("https://kemono.cr/fanbox/user/1/post/{}", id),
+                }
+            })
+            .collect::<Vec<_>>();
+        object! {
+            event: if new_posts == 0 { "failures" } else { "new_posts" },
+            creator: object! { url: "https://kemono.cr/fanbox/user/1", name: "someone" },
+            new_posts: posts,
+            files: object! { success: 3, skipped: 1, failed: 0 },
+            failures: JsonValue::new_array(),
+            error: JsonValue::Null,
+            exit_code: 0,
+        }
+    }
+
+    #[test]
+    fn json_body_is_the_notification() {
+        let notification = notification(1);
+        assert_eq!(
+            webhook(WebhookFormat::Json).body(&notification),
+            notification
+        );
+    }
+
+    #[test]
+    fn chat_message_lists_the_first_posts() {
+        let body = webhook(WebhookFormat::Slack).body(&notification(12));
+        let lines = body["text"].as_str().unwrap().lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "someone: 12 new posts");
+        assert_eq!(
+            lines[1],
+            "- post 1 <https://kemono.cr/fanbox/user/1/post/1>"
+        );
+        // posts without title are listed by id.
+        assert_eq!(lines[2], "- 2 <https://kemono.cr/fanbox/user/1/post/2>");
+        assert_eq!(lines.len(), 1 + LISTED_POSTS + 2);
+        assert_eq!(lines[LISTED_POSTS + 1], "and 2 more");
+        assert_eq!(
+            lines[LISTED_POSTS + 2],
+            "3 files downloaded, 1 skipped, 0 failed"
+        );
+    }
+
+    #[test]
+    fn failures_message_has_the_error() {
+        let mut notification = notification(0);
+        notification["files"]["failed"] = 2.into();
+        notification["error"] = "Not enough free space".into();
+        notification["creator"]["name"] = JsonValue::Null;
+        let body = webhook(WebhookFormat::Discord).body(&notification);
+        assert_eq!(
+            body["content"],
+            "https://kemono.cr/fanbox/user/1: run finished with failures\n\
+             3 files downloaded, 1 skipped, 2 failed\n\
+             Error: Not enough free space"
+        );
+    }
+
+    #[test]
+    fn discord_message_is_cut_to_its_limit() {
+        let mut notification = notification(1);
+        notification["error"] = "x".repeat(3 * DISCORD_MAX_CHARS).into();
+        let body = webhook(WebhookFormat::Discord).body(&notification);
+        assert_eq!(
+            body["content"].as_str().unwrap().chars().count(),
+            DISCORD_MAX_CHARS
+        );
+    }
+}